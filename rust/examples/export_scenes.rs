@@ -0,0 +1,14 @@
+//! Writes the scenes built in `scenes.rs` out to scene files in `scenes/`.
+
+use raytrace::{scene_file::save_scene, scenes};
+
+fn main() {
+    std::fs::create_dir_all("scenes").expect("Unable to create scenes directory");
+    for name in &["cornell_box", "volume_test", "book_final"] {
+        let scene = scenes::find(name).expect("no such built-in scene");
+        let path = format!("scenes/{}.ron", name);
+        save_scene(&scene.build(1, 1, 0xDEADBEEF), &path)
+            .unwrap_or_else(|e| panic!("Unable to write {}: {}", path, e));
+        println!("Wrote {}", path);
+    }
+}
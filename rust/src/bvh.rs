@@ -4,6 +4,7 @@ use crate::{
     aabb::Aabb,
     objects::{HitRecord, Object},
     ray::Ray,
+    scene_file::ObjectDesc,
     vec3::Axis::{self, *},
 };
 
@@ -124,4 +125,30 @@ impl Object for Bvh {
     fn bounding_box(&self, _exposure: Range<f64>) -> Aabb {
         self.bounding_box
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        fn leaves(bvh: &Bvh, out: &mut Vec<ObjectDesc>) -> Option<()> {
+            match &bvh.contents {
+                BvhContents::Node { left, right } => {
+                    leaves(left, out)?;
+                    leaves(right, out)
+                }
+                BvhContents::Leaf(object) => {
+                    out.push(object.describe()?);
+                    Some(())
+                }
+            }
+        }
+
+        let mut objects = Vec::with_capacity(self.size);
+        leaves(self, &mut objects)?;
+        Some(ObjectDesc::Group(objects))
+    }
+
+    fn faces(&self) -> usize {
+        match &self.contents {
+            BvhContents::Node { left, right } => left.faces() + right.faces(),
+            BvhContents::Leaf(object) => object.faces(),
+        }
+    }
 }
@@ -2,6 +2,7 @@ use rand::prelude::*;
 
 use crate::{
     ray::Ray,
+    scene_file::CameraDesc,
     vec3::{Axis::*, Vec3},
 };
 
@@ -52,6 +53,22 @@ impl Camera {
         }
     }
 
+    /// Describes the camera for a scene file. The description looks at the
+    /// center of the plane in focus, rather than at the original `look_at`.
+    pub fn describe(&self) -> CameraDesc {
+        let center = self.lower_left_corner + 0.5 * self.horizontal + 0.5 * self.vertical;
+        let focus_dist = (center - self.origin).length();
+        let half_height = 0.5 * self.vertical.length() / focus_dist;
+        CameraDesc {
+            look_from: self.origin,
+            look_at: center,
+            up: self.v,
+            fov: 2. * half_height.atan() * 180. / std::f64::consts::PI,
+            aperture: 2. * self.lens_radius,
+            focus_dist: Some(focus_dist),
+        }
+    }
+
     pub fn get_ray(&self, s: f64, t: f64, rng: &mut impl Rng) -> Ray {
         let rd = self.lens_radius * Vec3::in_unit_disc(rng);
         let offset = rd[X] * self.u + rd[Y] * self.v;
@@ -62,6 +79,7 @@ impl Camera {
                 - self.origin
                 - offset,
             time,
+            wavelength: None,
         }
     }
 }
@@ -0,0 +1,368 @@
+use rand::prelude::*;
+
+use crate::{
+    objects::{get_sphere_uv, HitRecord, PdfObject},
+    ray::Ray,
+    scene_file::EnvironmentDesc,
+    texture::{self, Texture},
+    vec3::{Axis::*, Vec3},
+    World, PI,
+};
+
+/// Light arriving from infinitely far away, seen by rays that escape the
+/// scene without hitting anything.
+#[derive(Clone, Debug)]
+pub enum Environment {
+    /// The same color in every direction.
+    Constant(Vec3),
+    /// A sky blending linearly from `bottom` straight down to `top` straight
+    /// up.
+    Gradient { bottom: Vec3, top: Vec3 },
+    /// A texture wrapped around the scene with a latitude-longitude
+    /// (equirectangular) mapping, using the same coordinates as `Sphere`,
+    /// turned `rotation` degrees around the Y axis like `rotate_y`.
+    Map {
+        texture: Texture,
+        brightness: f64,
+        rotation: f64,
+    },
+}
+
+impl Environment {
+    /// Loads a latitude-longitude environment map from an image file.
+    pub fn image<P: AsRef<std::path::Path>>(
+        filename: P,
+        brightness: f64,
+        // TODO: Remove boxed error
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Environment::Map {
+            texture: texture::image_texture(filename)?,
+            brightness,
+            rotation: 0.,
+        })
+    }
+
+    /// Describes the environment for a scene file, or returns `None` if its
+    /// texture can't be described.
+    pub fn describe(&self) -> Option<EnvironmentDesc> {
+        Some(match self {
+            Environment::Constant(color) => EnvironmentDesc::Constant(*color),
+            Environment::Gradient { bottom, top } => EnvironmentDesc::Gradient {
+                bottom: *bottom,
+                top: *top,
+            },
+            Environment::Map {
+                texture,
+                brightness,
+                rotation,
+            } => EnvironmentDesc::Map {
+                texture: texture.describe()?,
+                brightness: *brightness,
+                rotation: *rotation,
+            },
+        })
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn color(&self, direction: Vec3) -> Vec3 {
+        match self {
+            Environment::Constant(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.into_unit()[Y] + 1.);
+                (1. - t) * *bottom + t * *top
+            }
+            Environment::Map {
+                texture,
+                brightness,
+                rotation,
+            } => {
+                // As with `rotate_y`, only the texture coordinates turn; solid
+                // textures still see the direction in world space.
+                let (sin_theta, cos_theta) = rotation.to_radians().sin_cos();
+                let direction = direction.into_unit();
+                let (u, v) = get_sphere_uv(Vec3(
+                    cos_theta * direction[X] - sin_theta * direction[Z],
+                    direction[Y],
+                    sin_theta * direction[X] + cos_theta * direction[Z],
+                ));
+                *brightness * texture.value(u, v, direction)
+            }
+        }
+    }
+}
+
+/// Black, so that only the objects in the scene emit light.
+impl Default for Environment {
+    fn default() -> Self {
+        Environment::Constant(Vec3::default())
+    }
+}
+
+/// The objects of `world`, surrounded by `environment`.
+#[derive(Debug)]
+pub struct WithEnvironment<W> {
+    pub world: W,
+    pub environment: Environment,
+}
+
+impl<W: World> World for WithEnvironment<W> {
+    fn hit_top<'a>(&'a self, ray: &Ray, near: f64, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
+        self.world.hit_top(ray, near, rng)
+    }
+
+    fn background(&self, direction: Vec3) -> Vec3 {
+        self.environment.color(direction)
+    }
+}
+
+/// Importance sampling of the directions an `Environment` lights the scene
+/// from, for use as one of the lights of `LightSampling` or `Mis`.
+///
+/// The environment is tabulated on a latitude-longitude grid, and directions
+/// are drawn with probability proportional to the luminance of each cell times
+/// the solid angle it covers, so small bright features such as a sun are found
+/// by explicit samples. The grid should be about as fine as the features being
+/// sampled; for image maps, the image resolution is a good choice.
+#[derive(Debug, Clone)]
+pub struct EnvironmentLight {
+    width: usize,
+    height: usize,
+    /// Density of each cell over the `[0, 1)` x `[0, 1)` texture coordinates,
+    /// row-major with `v` selecting the row.
+    density: Vec<f64>,
+    /// Cumulative distribution of choosing each row.
+    marginal: Vec<f64>,
+    /// Cumulative distribution of choosing each cell within its row.
+    conditional: Vec<Vec<f64>>,
+}
+
+impl EnvironmentLight {
+    /// Tabulates `environment` on a `width` x `height` grid.
+    pub fn new(environment: &Environment, width: usize, height: usize) -> Self {
+        assert!(width > 0 && height > 0, "empty environment light grid");
+
+        let solid_angle = |j: usize| (PI * (j as f64 + 0.5) / height as f64).sin();
+        let mut weights: Vec<Vec<f64>> = (0..height)
+            .map(|j| {
+                let v = (j as f64 + 0.5) / height as f64;
+                (0..width)
+                    .map(|i| {
+                        let u = (i as f64 + 0.5) / width as f64;
+                        let luminance = environment.color(uv_direction(u, v)).luminance();
+                        // Cells near the poles cover less solid angle.
+                        luminance.max(0.) * solid_angle(j)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // A black environment is sampled uniformly instead.
+        if weights.iter().flatten().sum::<f64>() <= 0. {
+            weights = (0..height).map(|j| vec![solid_angle(j); width]).collect();
+        }
+
+        let row_weights: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+        let total: f64 = row_weights.iter().sum();
+
+        let cells = (width * height) as f64;
+        EnvironmentLight {
+            width,
+            height,
+            density: weights
+                .iter()
+                .flatten()
+                .map(|w| w / total * cells)
+                .collect(),
+            marginal: cumulative(&row_weights),
+            conditional: weights.iter().map(|row| cumulative(row)).collect(),
+        }
+    }
+
+    /// Tabulates `environment` if it is a map, on a grid of the map's image
+    /// resolution, or 512 x 256 for procedural maps. Constant and gradient
+    /// environments are smooth enough to be found without it.
+    pub fn for_map(environment: &Environment) -> Option<Self> {
+        match environment {
+            Environment::Map { texture, .. } => {
+                let (width, height) = texture.resolution().unwrap_or((512, 256));
+                Some(EnvironmentLight::new(environment, width, height))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl PdfObject for EnvironmentLight {
+    fn pdf_value(&self, _origin: Vec3, v: Vec3, _rng: &mut dyn FnMut() -> f64) -> f64 {
+        let (u, v) = get_sphere_uv(v.into_unit());
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        // Jacobian of the mapping from texture coordinates to solid angle.
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0. {
+            return 0.;
+        }
+        self.density[j * self.width + i] / (2. * PI * PI * sin_theta)
+    }
+
+    fn random(&self, _origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
+        let j = sample_cumulative(&self.marginal, rng(0., 1.));
+        let i = sample_cumulative(&self.conditional[j], rng(0., 1.));
+        let u = (i as f64 + rng(0., 1.)) / self.width as f64;
+        let v = (j as f64 + rng(0., 1.)) / self.height as f64;
+        uv_direction(u, v)
+    }
+}
+
+/// Inverse of `get_sphere_uv`: the unit direction with texture coordinates
+/// `u` and `v`.
+fn uv_direction(u: f64, v: f64) -> Vec3 {
+    let phi = PI - 2. * PI * u;
+    let theta = PI * v - PI / 2.;
+    Vec3(
+        theta.cos() * phi.cos(),
+        theta.sin(),
+        theta.cos() * phi.sin(),
+    )
+}
+
+/// Normalized cumulative distribution of `weights`, starting at 0 and ending
+/// at 1.
+fn cumulative(weights: &[f64]) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    let mut cdf = Vec::with_capacity(weights.len() + 1);
+    cdf.push(0.);
+    let mut sum = 0.;
+    for w in weights {
+        sum += w;
+        cdf.push(if total > 0. { sum / total } else { 0. });
+    }
+    cdf
+}
+
+/// Index of the bucket of the cumulative distribution `cdf` that `x` in
+/// `[0, 1)` falls into.
+fn sample_cumulative(cdf: &[f64], x: f64) -> usize {
+    cdf.partition_point(|&c| c <= x)
+        .saturating_sub(1)
+        .min(cdf.len() - 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        objects::{rotate_y, FlipNormals, Object, Sphere},
+        Material,
+    };
+    use rand::rngs::SmallRng;
+
+    /// An image texture of the given size, made by `f(x, y)`.
+    fn image(name: &str, width: u32, height: u32, f: impl Fn(u32, u32) -> u8) -> Texture {
+        let path =
+            std::env::temp_dir().join(format!("raytrace-{}-{}.png", std::process::id(), name));
+        image::RgbImage::from_fn(width, height, |x, y| image::Rgb([f(x, y); 3]))
+            .save(&path)
+            .unwrap();
+        let texture = texture::image_texture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        texture
+    }
+
+    #[test]
+    fn light_pdf_matches_samples() {
+        let environments = [
+            Environment::Gradient {
+                bottom: Vec3::from(0.01),
+                top: Vec3::from(5.),
+            },
+            // A dim sky with a small bright sun.
+            Environment::Map {
+                texture: image(
+                    "sun",
+                    32,
+                    16,
+                    |x, y| if (x, y) == (20, 4) { 255 } else { 2 },
+                ),
+                brightness: 1.,
+                rotation: 30.,
+            },
+        ];
+        let mut rng = SmallRng::seed_from_u64(7);
+        let n = 200_000;
+        for environment in &environments {
+            let light = EnvironmentLight::for_map(environment)
+                .unwrap_or_else(|| EnvironmentLight::new(environment, 16, 8));
+
+            // Directions drawn by `random` must have `pdf_value` as their
+            // density, so 1 / pdf averages to the area of the sphere.
+            let mut sum = 0.;
+            for _ in 0..n {
+                let direction = light.random(Vec3::default(), &mut |a, b| rng.gen_range(a..b));
+                sum += 1. / light.pdf_value(Vec3::default(), direction, &mut || 0.);
+            }
+            let mean = sum / n as f64;
+            assert!((mean / (4. * PI) - 1.).abs() < 0.02, "{}", mean);
+
+            // And `pdf_value` integrates to 1 over the sphere.
+            let mut sum = 0.;
+            let mut count = 0;
+            while count < n {
+                let direction = Vec3(
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                    rng.gen_range(-1. ..1.),
+                );
+                if direction.length() <= 1. {
+                    sum += light.pdf_value(Vec3::default(), direction, &mut || 0.);
+                    count += 1;
+                }
+            }
+            let mean = sum / n as f64 * 4. * PI;
+            assert!((mean - 1.).abs() < 0.02, "{}", mean);
+        }
+    }
+
+    #[test]
+    fn map_rotation_matches_rotate_y() {
+        let path =
+            std::env::temp_dir().join(format!("raytrace-{}-environment.png", std::process::id()));
+        image::RgbImage::from_fn(64, 32, |x, y| image::Rgb([(x * 4) as u8, (y * 8) as u8, 0]))
+            .save(&path)
+            .unwrap();
+        let texture = texture::image_texture(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let environment = Environment::Map {
+            texture: texture.clone(),
+            brightness: 1.,
+            rotation: 190.,
+        };
+        // The sky sphere environment maps replace.
+        let sky = rotate_y(
+            190.,
+            FlipNormals(Sphere {
+                center: Vec3::default(),
+                radius: 1000.,
+                material: Material::DiffuseLight {
+                    emission: texture,
+                    brightness: 1.,
+                },
+            }),
+        );
+        for &direction in &[
+            Vec3(1., 0.2, 0.3),
+            Vec3(-0.3, 0.5, 0.8),
+            Vec3(0.1, -0.7, -0.6),
+        ] {
+            let ray = Ray::new(Vec3::default(), direction, 0.);
+            let hit = sky.hit(&ray, 0.001..f64::MAX, &mut || 0.5).unwrap();
+            let emitted = match hit.material {
+                Material::DiffuseLight { emission, .. } => emission.value(hit.u, hit.v, hit.p),
+                _ => unreachable!(),
+            };
+            assert_eq!(environment.color(direction), emitted);
+        }
+    }
+}
@@ -0,0 +1,539 @@
+//! Minimal reader and writer for [OpenEXR][exr] images.
+//!
+//! The version of the `image` crate used here (0.23) has no OpenEXR codec.
+//! Later versions have one, but they bring in the `exr` crate and its
+//! dependencies and need a much newer compiler, which is a lot to take on
+//! for loading environment maps and saving renders.
+//!
+//! Only single-part scanline images are supported, uncompressed or with ZIP
+//! compression (`ZIPS` and `ZIP`), which covers what most renderers and
+//! compositors write by default. Tiled, deep and multi-part images are
+//! rejected with an error, as are the other compressions: `RLE`, `PIZ`,
+//! `PXR24`, `B44`, `B44A`, `DWAA` and `DWAB`. Images are always written
+//! uncompressed.
+//!
+//! [exr]: https://www.openexr.com/documentation/openexrfilelayout.pdf
+
+use std::{
+    convert::TryFrom,
+    error::Error,
+    io::{self, Write},
+};
+
+use crate::{image_texture::Texels, vec3::Vec3, Image};
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+
+/// Version field flag marking tiled images.
+const TILED: u32 = 0x200;
+/// Version field flags marking deep and multi-part images.
+const DEEP_OR_MULTIPART: u32 = 0x800 | 0x1000;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Compression {
+    None,
+    Zips,
+    Zip,
+}
+
+impl Compression {
+    fn scanlines_per_chunk(self) -> usize {
+        match self {
+            Compression::None | Compression::Zips => 1,
+            Compression::Zip => 16,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Uint | PixelType::Float => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+/// Cursor over the bytes of the file, reading little-endian values.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let bytes = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| format!("unexpected end of file at byte {}", self.pos))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    /// Moves to byte `pos`, which may be the end of the file but not past it.
+    fn seek(&mut self, pos: usize) -> Result<(), Box<dyn Error>> {
+        if pos > self.bytes.len() {
+            return Err(format!("offset {} is past the end of the file", pos).into());
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    fn i32(&mut self) -> Result<i32, Box<dyn Error>> {
+        Ok(self.u32()? as i32)
+    }
+
+    /// Reads a size or offset, which must not be negative.
+    fn size(&mut self) -> Result<usize, Box<dyn Error>> {
+        let at = self.pos;
+        let size = self.i32()?;
+        usize::try_from(size).map_err(|_| format!("negative size {} at byte {}", size, at).into())
+    }
+
+    fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    /// Reads a null-terminated string.
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let len = self.bytes[self.pos.min(self.bytes.len())..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| format!("unterminated string at byte {}", self.pos))?;
+        let s = String::from_utf8_lossy(self.take(len)?).into_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+}
+
+/// Reads the OpenEXR image in `bytes`, taking its `R`, `G` and `B` channels,
+/// or its `Y` channel for grayscale images.
+pub fn read(bytes: &[u8]) -> Result<Texels, Box<dyn Error>> {
+    let mut r = Reader { bytes, pos: 0 };
+
+    if r.take(4)? != MAGIC {
+        return Err("not an OpenEXR file".into());
+    }
+    let version = r.u32()?;
+    if version & TILED != 0 {
+        return Err("tiled OpenEXR images are not supported".into());
+    }
+    if version & DEEP_OR_MULTIPART != 0 {
+        return Err("deep and multi-part OpenEXR images are not supported".into());
+    }
+
+    let mut channels = vec![];
+    let mut compression = None;
+    let mut data_window = None;
+
+    loop {
+        let name = r.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = r.string()?;
+        let size = r.size()?;
+        let start = r.pos;
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => loop {
+                let name = r.string()?;
+                if name.is_empty() {
+                    break;
+                }
+                let pixel_type = match r.i32()? {
+                    0 => PixelType::Uint,
+                    1 => PixelType::Half,
+                    2 => PixelType::Float,
+                    t => {
+                        return Err(format!("unknown pixel type {} for channel {}", t, name).into())
+                    }
+                };
+                // pLinear, reserved bytes, and sampling rates.
+                r.take(4)?;
+                let (x_sampling, y_sampling) = (r.i32()?, r.i32()?);
+                if (x_sampling, y_sampling) != (1, 1) {
+                    return Err(format!("subsampled channel {} is not supported", name).into());
+                }
+                channels.push(Channel { name, pixel_type });
+            },
+            ("compression", "compression") => {
+                compression = Some(match r.u8()? {
+                    0 => Compression::None,
+                    2 => Compression::Zips,
+                    3 => Compression::Zip,
+                    c => {
+                        let name = match c {
+                            1 => "RLE",
+                            4 => "PIZ",
+                            5 => "PXR24",
+                            6 => "B44",
+                            7 => "B44A",
+                            8 => "DWAA",
+                            9 => "DWAB",
+                            _ => "unknown",
+                        };
+                        return Err(
+                            format!("unsupported OpenEXR compression {} ({})", c, name).into()
+                        );
+                    }
+                })
+            }
+            ("dataWindow", "box2i") => {
+                data_window = Some((r.i32()?, r.i32()?, r.i32()?, r.i32()?));
+            }
+            _ => {}
+        }
+        r.seek(start.checked_add(size).ok_or("attribute size overflows")?)?;
+    }
+
+    let compression = compression.ok_or("missing compression attribute")?;
+    let (x_min, y_min, x_max, y_max) = data_window.ok_or("missing dataWindow attribute")?;
+    if x_max < x_min || y_max < y_min {
+        return Err("empty dataWindow".into());
+    }
+    // Computed in 64 bits, as the difference of two `i32`s may not fit one.
+    let extent = |min: i32, max: i32| usize::try_from(max as i64 - min as i64 + 1);
+    let width = extent(x_min, x_max)?;
+    let height = extent(y_min, y_max)?;
+    // Every pixel takes at least two bytes, and deflate compresses by at most
+    // 1032:1, so a larger window can't be backed by the file.
+    let pixels = width
+        .checked_mul(height)
+        .filter(|&pixels| pixels / 516 <= bytes.len())
+        .ok_or("dataWindow is too large for the file")?;
+
+    let find = |name: &str| channels.iter().position(|c| c.name == name);
+    let rgb = match (find("R"), find("G"), find("B"), find("Y")) {
+        (Some(r), Some(g), Some(b), _) => [r, g, b],
+        (_, _, _, Some(y)) => [y, y, y],
+        _ => return Err("image has neither R, G, B nor Y channels".into()),
+    };
+
+    let scanline_size: usize = channels.iter().map(|c| c.pixel_type.size() * width).sum();
+    let per_chunk = compression.scanlines_per_chunk();
+    let chunks = height.div_ceil(per_chunk);
+
+    let offsets = (0..chunks)
+        .map(|_| Ok(usize::try_from(r.u64()?)?))
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+
+    // Within a scanline, each channel's samples are stored contiguously, in
+    // the order of the channel list.
+    let mut columns = vec![];
+    let mut start = 0;
+    for channel in &channels {
+        columns.push((start, channel.pixel_type));
+        start += channel.pixel_type.size() * width;
+    }
+
+    let mut data = vec![Vec3::default(); pixels];
+    for offset in offsets {
+        r.seek(offset)?;
+        let y = r.i32()?;
+        let y = usize::try_from(y as i64 - y_min as i64)
+            .ok()
+            .filter(|&y| y < height)
+            .ok_or_else(|| {
+                format!(
+                    "chunk at byte {} has scanline {} outside the image",
+                    offset, y
+                )
+            })?;
+        let size = r.size()?;
+        let packed = r.take(size)?;
+
+        let lines = per_chunk.min(height - y);
+        let expected = lines * scanline_size;
+        let block = if size == expected {
+            // Chunks that don't shrink when compressed are stored as is.
+            packed.to_vec()
+        } else {
+            match compression {
+                Compression::None => {
+                    return Err(format!("chunk at byte {} has the wrong size", offset).into())
+                }
+                Compression::Zips | Compression::Zip => unzip(packed)?,
+            }
+        };
+        if block.len() != expected {
+            return Err(format!("chunk at byte {} has the wrong size", offset).into());
+        }
+
+        for (line, scanline) in block.chunks(scanline_size).enumerate() {
+            let row = &mut data[(y + line) * width..(y + line + 1) * width];
+            for (x, texel) in row.iter_mut().enumerate() {
+                let channel = |c: usize| {
+                    let (start, pixel_type) = columns[c];
+                    let at = start + x * pixel_type.size();
+                    sample(&scanline[at..at + pixel_type.size()], pixel_type)
+                };
+                *texel = Vec3(channel(rgb[0]), channel(rgb[1]), channel(rgb[2]));
+            }
+        }
+    }
+
+    Ok(Texels {
+        width,
+        height,
+        data,
+    })
+}
+
+fn sample(bytes: &[u8], pixel_type: PixelType) -> f64 {
+    match pixel_type {
+        PixelType::Uint => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        PixelType::Half => half_to_f32(u16::from_le_bytes([bytes[0], bytes[1]])) as f64,
+        PixelType::Float => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+    }
+}
+
+/// Undoes ZIP compression: zlib inflation, then the delta predictor, then the
+/// split of the bytes into even and odd halves.
+fn unzip(packed: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = miniz_oxide::inflate::decompress_to_vec_zlib(packed)
+        .map_err(|e| format!("corrupt ZIP data: {:?}", e))?;
+
+    for i in 1..bytes.len() {
+        bytes[i] = bytes[i - 1].wrapping_add(bytes[i]).wrapping_sub(128);
+    }
+
+    let (even, odd) = bytes.split_at(bytes.len().div_ceil(2));
+    let mut interleaved = Vec::with_capacity(bytes.len());
+    for (i, &b) in even.iter().enumerate() {
+        interleaved.push(b);
+        if let Some(&b) = odd.get(i) {
+            interleaved.push(b);
+        }
+    }
+    Ok(interleaved)
+}
+
+/// Writes `image` as an uncompressed scanline OpenEXR image with 32-bit float
+/// `R`, `G` and `B` channels.
+pub fn write(out: &mut impl Write, image: &Image) -> io::Result<()> {
+    let (width, height) = (image.width() as i32, image.height() as i32);
+
+    fn attribute(out: &mut impl Write, name: &str, kind: &str, value: &[u8]) -> io::Result<()> {
+        out.write_all(name.as_bytes())?;
+        out.write_all(&[0])?;
+        out.write_all(kind.as_bytes())?;
+        out.write_all(&[0])?;
+        out.write_all(&(value.len() as i32).to_le_bytes())?;
+        out.write_all(value)
+    }
+
+    let mut header = vec![];
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    // Channels must be listed in alphabetical order.
+    let mut channels = vec![];
+    for name in &["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        // pLinear and reserved bytes.
+        channels.extend_from_slice(&[0; 4]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels)?;
+
+    attribute(&mut header, "compression", "compression", &[0])?;
+    let window: Vec<u8> = [0, 0, width - 1, height - 1]
+        .iter()
+        .flat_map(|x| x.to_le_bytes().to_vec())
+        .collect();
+    attribute(&mut header, "dataWindow", "box2i", &window)?;
+    attribute(&mut header, "displayWindow", "box2i", &window)?;
+    // Increasing y.
+    attribute(&mut header, "lineOrder", "lineOrder", &[0])?;
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    )?;
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8])?;
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    )?;
+    header.push(0);
+
+    // One chunk per scanline: its y coordinate, its size, then its samples.
+    let scanline_size = 3 * 4 * width as usize;
+    let chunk_size = 4 + 4 + scanline_size;
+    let first_chunk = header.len() + 8 * height as usize;
+
+    out.write_all(&header)?;
+    for y in 0..height as usize {
+        out.write_all(&((first_chunk + y * chunk_size) as u64).to_le_bytes())?;
+    }
+
+    for (y, row) in image.rows().enumerate() {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(scanline_size as i32).to_le_bytes())?;
+        for channel in &[|c: &Vec3| c.2, |c: &Vec3| c.1, |c: &Vec3| c.0] {
+            for col in row {
+                out.write_all(&(channel(col) as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Converts an IEEE 754 half-precision float to single precision.
+fn half_to_f32(h: u16) -> f32 {
+    let sign = ((h >> 15) as u32) << 31;
+    let exponent = ((h >> 10) & 0x1f) as u32;
+    let mantissa = (h & 0x3ff) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        (0, _) => {
+            // Subnormal: renormalize the mantissa.
+            let mut exponent = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x3ff) << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Image {
+        Image::compute(3, 2, |x, y| Vec3(x as f64, y as f64, 0.5))
+    }
+
+    fn encode(image: &Image) -> Vec<u8> {
+        let mut bytes = vec![];
+        write(&mut bytes, image).unwrap();
+        bytes
+    }
+
+    /// Byte offset of the first chunk, right after the header and the offset
+    /// table.
+    fn first_chunk(bytes: &[u8], height: usize) -> usize {
+        let end_of_header = bytes.len() - height * (8 + 3 * 3 * 4 + 8);
+        end_of_header + 8 * height
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = image();
+        let texels = read(&encode(&image)).unwrap();
+        assert_eq!((texels.width, texels.height), (3, 2));
+        let rows: Vec<Vec3> = image.rows().flatten().copied().collect();
+        assert_eq!(texels.data, rows);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = encode(&image());
+        for len in 0..bytes.len() {
+            assert!(read(&bytes[..len]).is_err(), "read {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn negative_attribute_size() {
+        let mut bytes = encode(&image());
+        // The size of the first attribute, "channels", follows its name and
+        // type.
+        let at = 8 + "channels\0chlist\0".len();
+        bytes[at..at + 4].copy_from_slice(&(-8i32).to_le_bytes());
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn offset_past_end() {
+        let mut bytes = encode(&image());
+        let at = first_chunk(&bytes, 2) - 16;
+        bytes[at..at + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn scanline_outside_image() {
+        let mut bytes = encode(&image());
+        let at = first_chunk(&bytes, 2);
+        for y in [2, -1, i32::MIN] {
+            bytes[at..at + 4].copy_from_slice(&y.to_le_bytes());
+            assert!(read(&bytes).is_err(), "scanline {}", y);
+        }
+    }
+
+    #[test]
+    fn huge_data_window() {
+        let mut bytes = encode(&image());
+        let window = [i32::MIN, i32::MIN, i32::MAX, i32::MAX];
+        let at = bytes
+            .windows(b"dataWindow\0box2i\0".len())
+            .position(|w| w == b"dataWindow\0box2i\0")
+            .unwrap()
+            + b"dataWindow\0box2i\0".len()
+            + 4;
+        for (i, x) in window.iter().enumerate() {
+            bytes[at + 4 * i..at + 4 * i + 4].copy_from_slice(&x.to_le_bytes());
+        }
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn unsupported_compression() {
+        let mut bytes = encode(&image());
+        let name = b"compression\0compression\0";
+        let at = bytes.windows(name.len()).position(|w| w == name).unwrap() + name.len() + 4;
+        // PIZ.
+        bytes[at] = 4;
+        let error = read(&bytes).err().unwrap().to_string();
+        assert!(error.contains("PIZ"), "{}", error);
+    }
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x3c00), 1.);
+        assert_eq!(half_to_f32(0xc000), -2.);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert!(half_to_f32(0x7c00).is_infinite());
+    }
+}
@@ -1,31 +1,209 @@
-use crate::vec3::Vec3;
-use image::flat::SampleLayout;
+use crate::{exr, vec3::Vec3};
 use std::convert::AsRef;
 
+/// An image decoded to floating point texels, so that high dynamic range
+/// images keep their full range.
+#[derive(Clone)]
+pub struct Texels {
+    pub width: usize,
+    pub height: usize,
+    /// Texels in row-major order, starting from the top left corner.
+    pub data: Vec<Vec3>,
+}
+
+/// Shows the size only, as the texels would flood the output.
+impl std::fmt::Debug for Texels {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Texels")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+/// Loads an image, picking the format by the file extension.
+///
+/// Radiance `.hdr` and OpenEXR `.exr` images are loaded as is; all other
+/// formats are loaded through the `image` crate and scaled to `[0, 1]`.
+/// Images without texels are rejected, as there would be nothing to map.
 pub fn load_image<P: AsRef<std::path::Path>>(
     path: P,
     // TODO: Remove boxed error
-) -> Result<(Vec<u8>, SampleLayout), Box<dyn std::error::Error>> {
-    let image = image::open(path)?.into_rgb8();
-    let image_description = image.sample_layout();
+) -> Result<Texels, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let texels = match extension.as_deref() {
+        Some("hdr") => {
+            let file = std::io::BufReader::new(std::fs::File::open(path)?);
+            let decoder = image::codecs::hdr::HdrDecoder::new(file)?;
+            let meta = decoder.metadata();
+            let data = decoder
+                .read_image_hdr()?
+                .into_iter()
+                .map(|p| Vec3(p[0] as f64, p[1] as f64, p[2] as f64))
+                .collect();
+            Texels {
+                width: meta.width as usize,
+                height: meta.height as usize,
+                data,
+            }
+        }
+        Some("exr") => exr::read(&std::fs::read(path)?)?,
+        _ => {
+            let image = image::open(path)?.into_rgb8();
+            let color_scale = 1.0 / 255.0;
+            Texels {
+                width: image.width() as usize,
+                height: image.height() as usize,
+                data: image
+                    .pixels()
+                    .map(|p| Vec3(p[0] as f64, p[1] as f64, p[2] as f64) * color_scale)
+                    .collect(),
+            }
+        }
+    };
+    if texels.width == 0 || texels.height == 0 {
+        return Err(format!("{}: image has no texels", path.display()).into());
+    }
+    Ok(texels)
+}
+
+/// How texture coordinates outside of `[0, 1]` map onto an image.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    /// Stretch the edge texels outwards.
+    #[default]
+    Clamp,
+    /// Tile the image.
+    Repeat,
+    /// Tile the image, mirroring every other tile.
+    MirroredRepeat,
+}
+
+impl Wrap {
+    /// Brings the coordinate `x` into `[0, 1]`.
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            Wrap::Clamp => x.clamp(0., 1.),
+            Wrap::Repeat => x.rem_euclid(1.),
+            Wrap::MirroredRepeat => 1. - (x.rem_euclid(2.) - 1.).abs(),
+        }
+    }
+}
+
+/// The texel at texture coordinates `u`, `v`, wrapping them by `wrap`.
+pub fn map_image(u: f64, v: f64, image: &Texels, [wrap_u, wrap_v]: [Wrap; 2]) -> Vec3 {
+    let u = wrap_u.apply(u);
+    let v = 1. - wrap_v.apply(v);
+
+    let i = ((u * image.width as f64) as usize).min(image.width - 1);
+    let j = ((v * image.height as f64) as usize).min(image.height - 1);
 
-    Ok((image.into_raw(), image_description))
+    image.data[j * image.width + i]
 }
 
-pub fn map_image(u: f64, v: f64, image: &[u8], img_desc: SampleLayout) -> Vec3 {
-    let channels = img_desc.channels as usize;
-    let width = img_desc.width as usize;
-    let height = img_desc.height as usize;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Image;
+
+    /// A path in the temporary directory unique to this test run.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("raytrace-{}-{}", std::process::id(), name))
+    }
+
+    fn image() -> Image {
+        Image::compute(4, 3, |x, y| Vec3(x as f64 + 0.5, y as f64 * 2., 100.))
+    }
+
+    fn round_trip(name: &str) -> Texels {
+        let path = temp_path(name);
+        image().save(&path).unwrap();
+        let texels = load_image(&path);
+        std::fs::remove_file(&path).unwrap();
+        texels.unwrap()
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let texels = round_trip("round_trip.exr");
+        let expected: Vec<Vec3> = image().rows().flatten().copied().collect();
+        assert_eq!((texels.width, texels.height), (4, 3));
+        assert_eq!(texels.data, expected);
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let texels = round_trip("round_trip.hdr");
+        assert_eq!((texels.width, texels.height), (4, 3));
+        for (texel, expected) in texels.data.iter().zip(image().rows().flatten()) {
+            // RGBE keeps 8 bits of mantissa shared across the channels.
+            assert!((*texel - *expected).length() < 1., "{:?}", texel);
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        for name in &["malformed.hdr", "malformed.exr", "malformed.png"] {
+            let path = temp_path(name);
+            std::fs::write(&path, b"#?RADIANCE\nnot really an image").unwrap();
+            let texels = load_image(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(texels.is_err(), "{}", name);
+        }
+    }
 
-    let u = u.max(0.).min(1.);
-    let v = 1. - v.max(0.).min(1.);
+    #[test]
+    fn empty() {
+        let path = temp_path("empty.hdr");
+        std::fs::write(&path, b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 0 +X 0\n").unwrap();
+        let texels = load_image(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(texels.is_err());
 
-    let i = (u * width as f64) as usize;
-    let j = (v * height as f64) as usize;
+        let path = temp_path("empty.exr");
+        Image::compute(0, 0, |_, _| Vec3::default())
+            .save(&path)
+            .unwrap();
+        let texels = load_image(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(texels.is_err());
+    }
 
-    let color_scale = 1.0 / 255.0;
-    let rel_idx = j * width * channels + i * channels;
-    let pixel = &image[rel_idx..rel_idx + 3];
+    #[test]
+    fn map_image_clamps() {
+        let texels = Texels {
+            width: 2,
+            height: 1,
+            data: vec![Vec3::from(0.), Vec3::from(1.)],
+        };
+        assert_eq!(
+            map_image(-1., 0.5, &texels, [Wrap::Clamp; 2]),
+            Vec3::from(0.)
+        );
+        assert_eq!(
+            map_image(2., 0.5, &texels, [Wrap::Clamp; 2]),
+            Vec3::from(1.)
+        );
+    }
 
-    Vec3(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64).map(|x| x * color_scale)
+    #[test]
+    fn map_image_wraps() {
+        let texels = Texels {
+            width: 2,
+            height: 1,
+            data: vec![Vec3::from(0.), Vec3::from(1.)],
+        };
+        let repeat = [Wrap::Repeat; 2];
+        assert_eq!(map_image(1.25, 0.5, &texels, repeat), Vec3::from(0.));
+        assert_eq!(map_image(-0.25, 0.5, &texels, repeat), Vec3::from(1.));
+        let mirror = [Wrap::MirroredRepeat; 2];
+        assert_eq!(map_image(1.25, 0.5, &texels, mirror), Vec3::from(1.));
+        assert_eq!(map_image(-0.25, 0.5, &texels, mirror), Vec3::from(0.));
+        assert_eq!(map_image(2.25, 0.5, &texels, mirror), Vec3::from(0.));
+    }
 }
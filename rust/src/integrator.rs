@@ -0,0 +1,682 @@
+use rand::prelude::*;
+
+use crate::{
+    material::Material,
+    objects::{HitRecord, PdfObject},
+    onb::Onb,
+    pdf::{self, Pdf},
+    ray::Ray,
+    settings::RenderSettings,
+    spectrum,
+    vec3::Vec3,
+    World,
+};
+
+/// A rendering algorithm: estimates the light arriving along a camera ray.
+///
+/// `par_cast` and `cast` take any `Integrator`, so the path tracers below can
+/// be swapped for one another, or for the debug views that render a single
+/// property of the first surface hit.
+pub trait Integrator: Send + Sync {
+    /// Estimates the color seen along `ray` in the scene `world`.
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3;
+}
+
+/// Plain path tracing with `ray_color`, following only material scattering.
+#[derive(Copy, Clone, Debug)]
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        ray_color(world, ray, settings, rng)
+    }
+}
+
+/// Path tracing that also samples `lights` directly, with
+/// `light_sampled_ray_color`.
+pub struct LightSampling {
+    pub lights: Vec<Box<dyn PdfObject>>,
+}
+
+impl Integrator for LightSampling {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        light_sampled_ray_color(world, &self.lights, ray, settings, rng)
+    }
+}
+
+/// Path tracing combining material and light sampling, with `mis_ray_color`.
+pub struct Mis {
+    pub lights: Vec<Box<dyn PdfObject>>,
+    pub heuristic: Heuristic,
+}
+
+impl Integrator for Mis {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        mis_ray_color(world, &self.lights, self.heuristic, ray, settings, rng)
+    }
+}
+
+/// Spectral path tracing with `spectral_ray_color`.
+#[derive(Copy, Clone, Debug)]
+pub struct Spectral;
+
+impl Integrator for Spectral {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        spectral_ray_color(world, ray, settings, rng)
+    }
+}
+
+/// Ambient occlusion: white where a random direction leaving the first surface
+/// hit escapes within `distance`, black where it is blocked.
+#[derive(Copy, Clone, Debug)]
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        let hit = match world.hit_top(&ray, settings.ray_epsilon, rng) {
+            Some(hit) => hit,
+            None => return Vec3::from(1.),
+        };
+
+        // Occlusion is measured on the side of the surface facing the camera.
+        let normal = if ray.direction.dot(hit.normal) > 0. {
+            -hit.normal
+        } else {
+            hit.normal
+        };
+        let direction = Onb::build_from_w(normal).local(pdf::random_cosine_dir(&mut || rng.gen()));
+        let probe = Ray::new(hit.p, direction.into_unit(), ray.time);
+
+        match world.hit_top(&probe, settings.ray_epsilon, rng) {
+            Some(blocker) if blocker.t < self.distance => Vec3::default(),
+            _ => Vec3::from(1.),
+        }
+    }
+}
+
+/// Debug view of the surface normals of the first surface hit, mapped from
+/// `[-1, 1]` to `[0, 1]` in each channel.
+#[derive(Copy, Clone, Debug)]
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        world
+            .hit_top(&ray, settings.ray_epsilon, rng)
+            .map(|hit| 0.5 * (hit.normal.into_unit() + 1.))
+            .unwrap_or_default()
+    }
+}
+
+/// Debug view of the texture coordinates of the first surface hit, with `u` in
+/// red and `v` in green.
+#[derive(Copy, Clone, Debug)]
+pub struct Uv;
+
+impl Integrator for Uv {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        world
+            .hit_top(&ray, settings.ray_epsilon, rng)
+            .map(|hit| Vec3(hit.u, hit.v, 0.))
+            .unwrap_or_default()
+    }
+}
+
+/// Debug view of the distance to the first surface hit, from black at the
+/// camera to white at `far` and beyond.
+#[derive(Copy, Clone, Debug)]
+pub struct Depth {
+    pub far: f64,
+}
+
+impl Integrator for Depth {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        world
+            .hit_top(&ray, settings.ray_epsilon, rng)
+            .map(|hit| Vec3::from((hit.t * ray.direction.length() / self.far).min(1.)))
+            .unwrap_or_else(|| Vec3::from(1.))
+    }
+}
+
+/// Debug view of the material color of the first surface hit, without any
+/// lighting.
+#[derive(Copy, Clone, Debug)]
+pub struct Albedo;
+
+impl Integrator for Albedo {
+    fn radiance(
+        &self,
+        world: &impl World,
+        ray: Ray,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Vec3 {
+        world
+            .hit_top(&ray, settings.ray_epsilon, rng)
+            .map(|hit| hit.material.albedo(&hit))
+            .unwrap_or_default()
+    }
+}
+
+/// Russian roulette: once a path has bounced `settings.roulette_depth` times,
+/// terminates it with a probability that grows as its throughput `attenuation`
+/// falls, and scales up the throughput of the paths that survive so the
+/// estimate remains unbiased.
+///
+/// Returns the new throughput, or `None` if the path should be terminated.
+fn roulette(
+    attenuation: Vec3,
+    bounces: usize,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
+) -> Option<Vec3> {
+    match settings.roulette_depth {
+        Some(depth) if bounces >= depth => {
+            // Keep a small chance of termination even for bright paths, so
+            // that paths trapped between mirrors still end.
+            let survival = attenuation.reduce(f64::max).min(0.95);
+            if rng.gen::<f64>() < survival {
+                Some(attenuation / survival)
+            } else {
+                None
+            }
+        }
+        _ => Some(attenuation),
+    }
+}
+
+/// Fraction of the light found further along a path that reaches the film.
+///
+/// Paths carry RGB colors until they commit to a single wavelength, from the
+/// start in `spectral_ray_color` or else at the first dispersive surface they
+/// reach. From then on colors are upsampled to their value at that
+/// wavelength, and light is only converted back to color as it reaches the
+/// film.
+#[derive(Copy, Clone, Debug)]
+enum Throughput {
+    Rgb(Vec3),
+    Spectral { lambda: f64, value: f64 },
+}
+
+impl Throughput {
+    /// Commits an RGB path to a random wavelength, which `ray` then carries,
+    /// if it is about to scatter off a dispersive `material`.
+    fn commit(&mut self, ray: &mut Ray, material: &Material, rng: &mut impl Rng) {
+        if let Throughput::Rgb(color) = *self {
+            if material.is_dispersive() {
+                let lambda = spectrum::sample_wavelength(rng);
+                ray.wavelength = Some(lambda);
+                *self = Throughput::Spectral {
+                    lambda,
+                    value: spectrum::rgb_to_spectrum(color, lambda),
+                };
+            }
+        }
+    }
+
+    /// Attenuates the path by the color `albedo`.
+    fn scale(self, albedo: Vec3) -> Self {
+        match self {
+            Throughput::Rgb(color) => Throughput::Rgb(color * albedo),
+            Throughput::Spectral { lambda, value } => Throughput::Spectral {
+                lambda,
+                value: value * spectrum::rgb_to_spectrum(albedo, lambda),
+            },
+        }
+    }
+
+    /// Color reaching the film from light of color `radiance` found along the
+    /// path.
+    fn light(self, radiance: Vec3) -> Vec3 {
+        match self {
+            Throughput::Rgb(color) => color * radiance,
+            Throughput::Spectral { lambda, value } => {
+                value
+                    * spectrum::rgb_to_spectrum(radiance, lambda)
+                    * spectrum::wavelength_weight(lambda)
+            }
+        }
+    }
+
+    /// Color reaching the film from the light emitted at `hit`, which is taken
+    /// from the emitter's spectrum once the path has a wavelength.
+    fn emitted(self, hit: &HitRecord) -> Vec3 {
+        match self {
+            Throughput::Rgb(color) => color * hit.material.emitted(hit.u, hit.v, hit.p, hit),
+            Throughput::Spectral { lambda, value } => {
+                value
+                    * hit
+                        .material
+                        .emitted_spectral(lambda, hit.u, hit.v, hit.p, hit)
+                    * spectrum::wavelength_weight(lambda)
+            }
+        }
+    }
+
+    /// Plays `roulette` with the throughput. Upsampled spectra can dip below
+    /// zero, so a single wavelength plays with its magnitude.
+    fn roulette(
+        self,
+        bounces: usize,
+        settings: &RenderSettings,
+        rng: &mut impl Rng,
+    ) -> Option<Self> {
+        match self {
+            Throughput::Rgb(color) => roulette(color, bounces, settings, rng).map(Throughput::Rgb),
+            Throughput::Spectral { lambda, value } => {
+                roulette(Vec3::from(value.abs()), bounces, settings, rng).map(|kept| {
+                    Throughput::Spectral {
+                        lambda,
+                        value: value.signum() * kept.0,
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Computes the pixel color along `ray` for the scene of objects `world`.
+///
+/// This is the actual ray-tracing routine.
+pub fn ray_color(
+    world: &impl World,
+    ray: Ray,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
+) -> Vec3 {
+    trace(world, ray, Throughput::Rgb(Vec3::from(1.)), settings, rng)
+}
+
+/// Like `ray_color`, but follows the path at a single random wavelength, with
+/// RGB colors upsampled to spectra and emitters contributing their actual
+/// spectra. The result is converted back to color through the CIE color
+/// matching functions.
+pub fn spectral_ray_color(
+    world: &impl World,
+    mut ray: Ray,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
+) -> Vec3 {
+    let lambda = spectrum::sample_wavelength(rng);
+    ray.wavelength = Some(lambda);
+    trace(
+        world,
+        ray,
+        Throughput::Spectral { lambda, value: 1. },
+        settings,
+        rng,
+    )
+}
+
+/// The path tracing loop of `ray_color` and `spectral_ray_color`.
+fn trace(
+    world: &impl World,
+    mut ray: Ray,
+    mut throughput: Throughput,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
+) -> Vec3 {
+    // Accumulates contribution of each surface we reach.
+    let mut accum = Vec3::default();
+
+    let mut bounces = 0;
+
+    // Iterate until one of the following conditions is reached:
+    // 1. The ray escapes into space (i.e. no objects are hit).
+    // 2. The ray reaches a surface that does not scatter.
+    // 3. The ray bounces more than `settings.max_depth` times.
+    // 4. The path is terminated by Russian roulette.
+    while let Some(hit) = world.hit_top(&ray, settings.ray_epsilon, rng) {
+        // Record this hit's contribution, attenuated by the total attenuation
+        // so far.
+        accum = accum + throughput.emitted(&hit);
+
+        // Check whether the material scatters light, generating a new ray. In
+        // practice this is true for everything but the emission-only
+        // DiffuseLight type.
+        //
+        // TODO(#4): and also for frosted metal, which effectively makes frosted
+        // metal an emitter. That can't be right.
+        throughput.commit(&mut ray, hit.material, rng);
+        if let Some((scattered, albedo, _pdf)) = hit.material.scatter(&ray, &hit, rng) {
+            // Redirect flight, accumulate the new attenuation value.
+            throughput = throughput.scale(albedo);
+            ray = scattered;
+        } else {
+            // Locally absorbed; we're done.
+            return accum;
+        }
+
+        // Randomly terminate dim paths, boosting the survivors to compensate.
+        throughput = match throughput.roulette(bounces, settings, rng) {
+            Some(throughput) => throughput,
+            None => return accum,
+        };
+
+        if bounces == settings.max_depth {
+            return accum;
+        }
+
+        bounces += 1;
+    }
+
+    // Escaped; pick up whatever light arrives from the surroundings.
+    accum + throughput.light(world.background(ray.direction))
+}
+
+/// Computes the pixel color along `ray` like `ray_color`, but additionally
+/// samples the emitters in `lights` directly.
+///
+/// At diffuse and rough surfaces the scattered direction is drawn from an even
+/// mixture of the material's own density and a density towards `lights`, and
+/// weighted by `Material::eval` over the mixture density. Materials without a
+/// scattering density, like smooth metal and glass, follow their own scattered
+/// ray.
+pub fn light_sampled_ray_color(
+    world: &impl World,
+    lights: &[Box<dyn PdfObject>],
+    mut ray: Ray,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
+) -> Vec3 {
+    let mut accum = Vec3::default();
+    let mut throughput = Throughput::Rgb(Vec3::from(1.));
+
+    let mut bounces = 0;
+
+    while let Some(hit) = world.hit_top(&ray, settings.ray_epsilon, rng) {
+        accum = accum + throughput.emitted(&hit);
+
+        throughput.commit(&mut ray, hit.material, rng);
+        let (scattered, albedo, pdf) = match hit.material.scatter(&ray, &hit, rng) {
+            Some(scatter) => scatter,
+            None => return accum,
+        };
+
+        // Only materials reporting a density can be importance sampled; for
+        // the rest, the albedo already accounts for the chosen direction.
+        if pdf > 0. && !lights.is_empty() {
+            // Either keep the direction the material sampled, or replace it
+            // with one towards the lights.
+            let light_pdf = Pdf::hittable(&lights, hit.p);
+            let direction = if rng.gen::<f64>() < 0.5 {
+                light_pdf
+                    .generate(&mut |lo, hi| rng.gen_range(lo..hi))
+                    .into_unit()
+            } else {
+                scattered.direction
+            };
+            let scattered = Ray {
+                origin: hit.p,
+                direction,
+                ..ray
+            };
+            let pdf = 0.5 * light_pdf.value(direction, &mut || rng.gen())
+                + 0.5 * hit.material.scattering_pdf(&ray, &hit, &scattered);
+            if pdf <= 0. {
+                return accum;
+            }
+
+            throughput = throughput.scale(hit.material.eval(&ray, &hit, &scattered) / pdf);
+            ray = scattered;
+        } else {
+            throughput = throughput.scale(albedo);
+            ray = scattered;
+        }
+
+        // Randomly terminate dim paths, boosting the survivors to compensate.
+        throughput = match throughput.roulette(bounces, settings, rng) {
+            Some(throughput) => throughput,
+            None => return accum,
+        };
+
+        if bounces == settings.max_depth {
+            return accum;
+        }
+
+        bounces += 1;
+    }
+
+    accum + throughput.light(world.background(ray.direction))
+}
+
+/// Weighting heuristics for combining samples with multiple importance
+/// sampling, after Veach.
+#[derive(Copy, Clone, Debug)]
+pub enum Heuristic {
+    /// Weights samples proportionally to their densities.
+    Balance,
+    /// Weights samples proportionally to their squared densities, which
+    /// further suppresses the strategy that is a poor fit.
+    Power,
+}
+
+impl Heuristic {
+    /// Weight of a sample drawn with density `pdf` when the other strategy
+    /// would have drawn it with density `other_pdf`.
+    pub fn weight(self, pdf: f64, other_pdf: f64) -> f64 {
+        let (f, g) = match self {
+            Heuristic::Balance => (pdf, other_pdf),
+            Heuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if f + g > 0. {
+            f / (f + g)
+        } else {
+            0.
+        }
+    }
+}
+
+/// Computes the pixel color along `ray` combining material (BSDF) sampling and
+/// sampling of the emitters in `lights` with multiple importance sampling.
+///
+/// At each diffuse or rough surface, one direction is drawn towards `lights` and traced
+/// as a shadow ray, and another is drawn from the material to continue the
+/// path. Emission found along either one is weighted by `heuristic`, so that
+/// each strategy dominates where it has the higher density: light sampling for
+/// small bright emitters, material sampling for large or glossy ones.
+pub fn mis_ray_color(
+    world: &impl World,
+    lights: &[Box<dyn PdfObject>],
+    heuristic: Heuristic,
+    mut ray: Ray,
+    settings: &RenderSettings,
+    rng: &mut impl Rng,
+) -> Vec3 {
+    let mut accum = Vec3::default();
+    let mut throughput = Throughput::Rgb(Vec3::from(1.));
+    // Density with which the material sampled `ray`, if it was sampled at a
+    // surface where lights were also sampled explicitly.
+    let mut bsdf_pdf = None;
+
+    let mut bounces = 0;
+
+    while let Some(hit) = world.hit_top(&ray, settings.ray_epsilon, rng) {
+        let emitted = throughput.emitted(&hit);
+        if emitted.dot(emitted) > 0. {
+            let weight = bsdf_weight(lights, heuristic, &ray, bsdf_pdf, rng);
+            accum = accum + emitted * weight;
+        }
+
+        throughput.commit(&mut ray, hit.material, rng);
+        let (scattered, albedo, pdf) = match hit.material.scatter(&ray, &hit, rng) {
+            Some(scatter) => scatter,
+            None => return accum,
+        };
+
+        if pdf > 0. && !lights.is_empty() {
+            let light = Pdf::hittable(&lights, hit.p);
+            let direction = light
+                .generate(&mut |lo, hi| rng.gen_range(lo..hi))
+                .into_unit();
+            let light_pdf = light.value(direction, &mut || rng.gen());
+            let shadow = Ray {
+                origin: hit.p,
+                direction,
+                ..ray
+            };
+
+            if light_pdf > 0. {
+                let scattering_pdf = hit.material.scattering_pdf(&ray, &hit, &shadow);
+                let weight = heuristic.weight(light_pdf, scattering_pdf);
+                let throughput =
+                    throughput.scale(hit.material.eval(&ray, &hit, &shadow) * (weight / light_pdf));
+                // Shadow rays that escape pick up the environment, which may
+                // itself be one of the lights.
+                accum = accum
+                    + match world.hit_top(&shadow, settings.ray_epsilon, rng) {
+                        Some(light_hit) => throughput.emitted(&light_hit),
+                        None => throughput.light(world.background(direction)),
+                    };
+            }
+
+            bsdf_pdf = Some(pdf);
+        } else {
+            bsdf_pdf = None;
+        }
+
+        throughput = throughput.scale(if pdf > 0. {
+            hit.material.eval(&ray, &hit, &scattered) / pdf
+        } else {
+            albedo
+        });
+        ray = scattered;
+
+        // Randomly terminate dim paths, boosting the survivors to compensate.
+        throughput = match throughput.roulette(bounces, settings, rng) {
+            Some(throughput) => throughput,
+            None => return accum,
+        };
+
+        if bounces == settings.max_depth {
+            return accum;
+        }
+
+        bounces += 1;
+    }
+
+    let weight = bsdf_weight(lights, heuristic, &ray, bsdf_pdf, rng);
+    accum + throughput.light(world.background(ray.direction)) * weight
+}
+
+/// Weight of light found along `ray` after the material sampled it with
+/// density `bsdf_pdf`, where `None` means `lights` were not sampled and the
+/// light counts in full.
+fn bsdf_weight(
+    lights: &[Box<dyn PdfObject>],
+    heuristic: Heuristic,
+    ray: &Ray,
+    bsdf_pdf: Option<f64>,
+    rng: &mut impl Rng,
+) -> f64 {
+    match bsdf_pdf {
+        Some(bsdf_pdf) => {
+            let light_pdf = lights.pdf_value(ray.origin, ray.direction, &mut || rng.gen());
+            heuristic.weight(bsdf_pdf, light_pdf)
+        }
+        None => 1.,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::{Environment, WithEnvironment},
+        objects::{Object, Sphere},
+        spectrum::Dispersion,
+    };
+    use rand::rngs::SmallRng;
+
+    #[test]
+    fn dispersion_in_white_furnace() {
+        // Clear glass under a uniformly white sky passes all light on, so
+        // whatever wavelengths the paths commit to, they average to white.
+        let objects: Vec<Box<dyn Object>> = vec![Box::new(Sphere {
+            center: Vec3::default(),
+            radius: 1.,
+            material: Material::Dispersive {
+                dispersion: Dispersion::SF11,
+                roughness: 0.,
+                absorption: Vec3::default(),
+            },
+        })];
+        let world = WithEnvironment {
+            world: &objects[..],
+            environment: Environment::Gradient {
+                bottom: Vec3::from(1.),
+                top: Vec3::from(1.),
+            },
+        };
+        let settings = RenderSettings::default();
+        let mut rng = SmallRng::seed_from_u64(2);
+        let n = 50_000;
+        for integrator in &[ray_color, spectral_ray_color] {
+            let mut sum = Vec3::default();
+            for _ in 0..n {
+                let target = Vec3(rng.gen_range(-0.7..0.7), rng.gen_range(-0.7..0.7), 0.);
+                let origin = Vec3(0., 0., -5.);
+                let ray = Ray::new(origin, target - origin, 0.);
+                sum = sum + integrator(&world, ray, &settings, &mut rng);
+            }
+            let mean = sum / n as f64;
+            assert!((mean - Vec3::from(1.)).length() < 0.03, "{:?}", mean);
+        }
+    }
+}
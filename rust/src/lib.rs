@@ -1,15 +1,25 @@
 mod aabb;
 pub mod bvh;
 pub mod camera;
+pub mod environment;
+mod exr;
 mod image_texture;
+pub mod integrator;
+pub mod loaders;
 pub mod material;
+mod microfacet;
 pub mod objects;
 mod onb;
+pub mod output;
 mod pdf;
 mod perlin;
 pub mod ray;
+pub mod scene_file;
 pub mod scenes;
+pub mod settings;
+pub mod spectrum;
 pub mod texture;
+pub mod tonemap;
 pub mod vec3;
 
 use rand::prelude::*;
@@ -17,37 +27,56 @@ use rayon::prelude::*;
 
 pub use crate::{
     camera::Camera,
-    material::Material,
+    environment::{Environment, EnvironmentLight, WithEnvironment},
+    integrator::{
+        light_sampled_ray_color, mis_ray_color, ray_color, spectral_ray_color, Heuristic,
+        Integrator, PathTracer, Spectral,
+    },
+    material::{ComplexIor, Material},
     objects::{
-        rect_prism, rotate_y, FlipNormals, HitRecord, Object, PdfObject, Rect, Sphere, StaticX,
-        StaticY, StaticZ, Translate,
+        rect_prism, rotate_y, FlipNormals, HitRecord, Mesh, MeshData, Object, PdfObject, Rect,
+        Sphere, StaticX, StaticY, StaticZ, Translate, Triangle,
     },
+    output::{write_exr, write_hdr, write_pfm},
     pdf::Pdf,
     ray::Ray,
+    settings::RenderSettings,
+    spectrum::Dispersion,
+    tonemap::{DisplayTransform, ToneMap},
     vec3::{Channel::*, *},
 };
 
 pub use std::f64::consts::{FRAC_PI_2, PI, TAU};
 
 pub trait World: Send + Sync {
-    fn hit_top<'a>(&'a self, ray: &Ray, rng: &mut impl Rng) -> Option<HitRecord<'a>>;
+    /// Finds the nearest object hit by `ray`, ignoring any hits closer than
+    /// `near`.
+    fn hit_top<'a>(&'a self, ray: &Ray, near: f64, rng: &mut impl Rng) -> Option<HitRecord<'a>>;
+
+    /// Light arriving along `direction` from beyond the objects of the world,
+    /// seen by rays that hit nothing.
+    fn background(&self, _direction: Vec3) -> Vec3 {
+        Vec3::default()
+    }
 }
 
 impl<'r, T: World + ?Sized> World for &'r T {
-    fn hit_top<'a>(&'a self, ray: &Ray, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
-        (*self).hit_top(ray, rng)
+    fn hit_top<'a>(&'a self, ray: &Ray, near: f64, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
+        (*self).hit_top(ray, near, rng)
+    }
+
+    fn background(&self, direction: Vec3) -> Vec3 {
+        (*self).background(direction)
     }
 }
 
 impl World for [Box<dyn Object>] {
-    fn hit_top<'a>(&'a self, ray: &Ray, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
-        const NEAR: f64 = 0.001;
-
+    fn hit_top<'a>(&'a self, ray: &Ray, near: f64, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
         let mut nearest = f64::MAX;
         let mut hit = None;
 
         for obj in self {
-            if let Some(rec) = obj.hit(ray, NEAR..nearest, &mut || rng.gen()) {
+            if let Some(rec) = obj.hit(ray, near..nearest, &mut || rng.gen()) {
                 nearest = rec.t;
                 hit = Some(rec);
             }
@@ -58,58 +87,28 @@ impl World for [Box<dyn Object>] {
 }
 
 impl World for bvh::Bvh {
-    fn hit_top<'a>(&'a self, ray: &Ray, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
-        self.hit(ray, 0.001..f64::MAX, &mut || rng.gen())
+    fn hit_top<'a>(&'a self, ray: &Ray, near: f64, rng: &mut impl Rng) -> Option<HitRecord<'a>> {
+        self.hit(ray, near..f64::MAX, &mut || rng.gen())
     }
 }
 
-/// Computes the pixel color along `ray` for the scene of objects `world`.
-///
-/// This is the actual ray-tracing routine.
-pub fn ray_color(world: &impl World, mut ray: Ray, rng: &mut impl Rng) -> Vec3 {
-    // Accumulates contribution of each surface we reach.
-    let mut accum = Vec3::default();
-    // Records the cumulative (product) attenuation of each surface we've
-    // visited so far.
-    let mut attenuation = Vec3::from(1.);
-
-    let mut bounces = 0;
-
-    // Iterate until one of the following conditions is reached:
-    // 1. The ray escapes into space (i.e. no objects are hit).
-    // 2. The ray reaches a surface that does not scatter.
-    // 3. The ray bounces more than 50 times.
-    while let Some(hit) = world.hit_top(&ray, rng) {
-        // Record this hit's contribution, attenuated by the total attenuation
-        // so far.
-        accum = accum + attenuation * hit.material.emitted(hit.u, hit.v, hit.p, &hit);
-
-        // Check whether the material scatters light, generating a new ray. In
-        // practice this is true for everything but the emission-only
-        // DiffuseLight type.
-        //
-        // TODO(#4): and also for frosted metal, which effectively makes frosted
-        // metal an emitter. That can't be right.
-        if let Some((scattered, albedo, _pdf)) = hit.material.scatter(&ray, &hit, rng) {
-            // Redirect flight, accumulate the new attenuation value.
-            attenuation = attenuation * albedo;
-            ray = scattered;
-        } else {
-            // Locally absorbed; we're done.
-            return accum;
-        }
-
-        // TODO(#6): Add `depth` as configureable argument as maximum
-        // number of bounces
-        if bounces == 50 {
-            return accum;
-        }
-
-        bounces += 1;
+/// The ceiling light of the Cornell box.
+fn cornell_box_light() -> Rect<StaticY> {
+    Rect {
+        orthogonal_to: StaticY,
+        range0: 213. ..343.,
+        range1: 227. ..332.,
+        k: 554.,
+        material: Material::DiffuseLight {
+            emission: texture::constant(Vec3::from(1.)),
+            brightness: 15.,
+        },
     }
+}
 
-    // TODO: Add background color
-    Vec3::default()
+/// Emitters of `cornell_box`, for use with `light_sampled_ray_color`.
+pub fn cornell_box_lights() -> Vec<Box<dyn PdfObject>> {
+    vec![Box::new(cornell_box_light())]
 }
 
 pub fn cornell_box() -> Vec<Box<dyn Object>> {
@@ -122,18 +121,8 @@ pub fn cornell_box() -> Vec<Box<dyn Object>> {
     let red = diffuse_color(Vec3(0.65, 0.05, 0.05));
     let white = diffuse_color(Vec3::from(0.73));
     let green = diffuse_color(Vec3(0.12, 0.45, 0.15));
-    let light = Material::DiffuseLight {
-        emission: texture::constant(Vec3::from(1.)),
-        brightness: 15.,
-    };
     vec![
-        Box::new(Rect {
-            orthogonal_to: StaticY,
-            range0: 213. ..343.,
-            range1: 227. ..332.,
-            k: 554.,
-            material: light,
-        }),
+        Box::new(cornell_box_light()),
         // floor
         Box::new(Rect {
             orthogonal_to: StaticY,
@@ -290,7 +279,10 @@ pub fn random_scene(rng: &mut impl Rng) -> Vec<Object> {
                     Sphere {
                         center,
                         radius: 0.2,
-                        material: Material::Dielectric { ref_idx: 1.5 },
+                        material: Material::Dielectric {
+                            ref_idx: 1.5,
+                            absorption: Vec3::default(),
+                        },
                         motion: Vec3::default(),
                     }
                 };
@@ -302,7 +294,10 @@ pub fn random_scene(rng: &mut impl Rng) -> Vec<Object> {
     world.push(Sphere {
         center: Vec3(0., 1., 0.),
         radius: 1.0,
-        material: Material::Dielectric { ref_idx: 1.5 },
+        material: Material::Dielectric {
+            ref_idx: 1.5,
+            absorption: Vec3::default(),
+        },
         motion: Vec3::default(),
     });
 
@@ -330,9 +325,24 @@ pub fn random_scene(rng: &mut impl Rng) -> Vec<Object> {
 }
 */
 
+/// A rendered image of linear radiance values, stored as rows from top to
+/// bottom.
 pub struct Image(Vec<Vec<Vec3>>);
 
 impl Image {
+    pub fn width(&self) -> usize {
+        self.0.first().map_or(0, Vec::len)
+    }
+
+    pub fn height(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Iterates over the rows of pixels, from top to bottom.
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Vec3]> + '_ {
+        self.0.iter().map(Vec::as_slice)
+    }
+
     pub fn par_compute(nx: usize, ny: usize, f: impl Fn(usize, usize) -> Vec3 + Sync) -> Image {
         Image(
             (0..ny)
@@ -353,7 +363,9 @@ impl Image {
     }
 }
 
-pub fn print_ppm(image: Image) {
+/// Writes `image` to standard output as an ASCII PPM, after applying
+/// `display`.
+pub fn print_ppm(image: Image, display: &DisplayTransform) {
     use std::io::Write;
     let mut writer = std::io::BufWriter::new(std::io::stdout());
     writer
@@ -362,15 +374,7 @@ pub fn print_ppm(image: Image) {
 
     for scanline in image.0 {
         for col in scanline {
-            let col = Vec3(col.0.sqrt(), col.1.sqrt(), col.2.sqrt());
-
-            fn to_u8(x: f64) -> i32 {
-                ((255.99 * x) as i32).max(0).min(255)
-            }
-
-            let ir = to_u8(col[R]);
-            let ig = to_u8(col[G]);
-            let ib = to_u8(col[B]);
+            let [ir, ig, ib] = display.to_rgb8(col);
 
             writer
                 .write_all(format!("{} {} {}\n", ir, ig, ib).as_bytes())
@@ -379,38 +383,103 @@ pub fn print_ppm(image: Image) {
     }
 }
 
-pub fn par_cast(nx: usize, ny: usize, ns: usize, camera: &Camera, world: impl World) -> Image {
-    Image::par_compute(nx, ny, |x, y| {
-        let col: Vec3 = (0..ns)
-            .map(|_| {
-                let mut rng = thread_rng();
-                let u = (x as f64 + rng.gen::<f64>()) / nx as f64;
-                let v = (y as f64 + rng.gen::<f64>()) / ny as f64;
-                let r = camera.get_ray(u, v, &mut rng);
-                ray_color(&world, r, &mut rng)
-            })
-            .sum();
-        col / ns as f64
-    })
+pub fn par_cast(
+    settings: &RenderSettings,
+    camera: &Camera,
+    world: impl World,
+    integrator: &impl Integrator,
+) -> Image {
+    let (nx, ny, ns) = (settings.width, settings.height, settings.samples);
+    let render = || {
+        Image::par_compute(nx, ny, |x, y| {
+            let col: Vec3 = (0..ns)
+                .map(|sample| {
+                    let mut rng = settings.sample_rng(x, y, sample);
+                    let u = (x as f64 + rng.gen::<f64>()) / nx as f64;
+                    let v = (y as f64 + rng.gen::<f64>()) / ny as f64;
+                    let r = camera.get_ray(u, v, &mut rng);
+                    integrator.radiance(&world, r, settings, &mut rng)
+                })
+                .sum();
+            col / ns as f64
+        })
+    };
+
+    match settings.threads {
+        Some(threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .expect("failed to start render threads")
+            .install(render),
+        None => render(),
+    }
 }
 
+/// Renders on the calling thread. The result is identical to that of
+/// `par_cast`, as both draw each sample from `RenderSettings::sample_rng`.
 pub fn cast(
-    nx: usize,
-    ny: usize,
-    ns: usize,
+    settings: &RenderSettings,
     camera: &Camera,
     world: impl World,
-    rng: &mut impl Rng,
+    integrator: &impl Integrator,
 ) -> Image {
+    let (nx, ny, ns) = (settings.width, settings.height, settings.samples);
     Image::compute(nx, ny, |x, y| {
         let col: Vec3 = (0..ns)
-            .map(|_| {
+            .map(|sample| {
+                let mut rng = settings.sample_rng(x, y, sample);
                 let u = (x as f64 + rng.gen::<f64>()) / nx as f64;
                 let v = (y as f64 + rng.gen::<f64>()) / ny as f64;
-                let r = camera.get_ray(u, v, rng);
-                ray_color(&world, r, rng)
+                let r = camera.get_ray(u, v, &mut rng);
+                integrator.radiance(&world, r, settings, &mut rng)
             })
             .sum();
         col / ns as f64
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(image: &Image) -> Vec<[u64; 3]> {
+        image
+            .rows()
+            .flatten()
+            .map(|c| [c.0.to_bits(), c.1.to_bits(), c.2.to_bits()])
+            .collect()
+    }
+
+    #[test]
+    fn renders_are_deterministic() {
+        let (nx, ny) = (12, 9);
+        let scene = scenes::volume_test(nx, ny);
+        let camera = scene.camera;
+        let world = bvh::Bvh::new(scene.objects, scene.exposure);
+        let integrator = integrator::Mis {
+            lights: scene.lights,
+            heuristic: Heuristic::Power,
+        };
+        let render = |threads| {
+            let settings = RenderSettings {
+                width: nx,
+                height: ny,
+                samples: 4,
+                roulette_depth: Some(3),
+                threads,
+                ..RenderSettings::default()
+            };
+            (
+                cast(&settings, &camera, &world, &integrator),
+                par_cast(&settings, &camera, &world, &integrator),
+            )
+        };
+
+        let (serial, one) = render(Some(1));
+        let (_, many) = render(Some(4));
+        let expected = bits(&serial);
+        assert!(expected.iter().any(|&c| c != [0; 3]), "image is black");
+        assert_eq!(bits(&one), expected);
+        assert_eq!(bits(&many), expected);
+    }
+}
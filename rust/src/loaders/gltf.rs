@@ -0,0 +1,638 @@
+//! Loader for [glTF 2.0][gltf] scenes, in `.gltf` or binary `.glb` form.
+//!
+//! [gltf]: https://www.khronos.org/gltf/
+
+use std::{collections::HashMap, path::Path};
+
+use gltf::{
+    camera::Projection,
+    image::{Data, Format},
+    mesh::Mode,
+    texture::WrappingMode,
+};
+
+use crate::{
+    camera::Camera,
+    image_texture::{Texels, Wrap},
+    loaders::{LoadError, Position},
+    objects::{Mesh, MeshData, Object, PdfObject},
+    texture::{self, Texture},
+    tonemap::srgb_decode,
+    vec3::Vec3,
+    Material,
+};
+
+/// Objects and camera loaded from a glTF file.
+pub struct GltfScene {
+    /// One mesh per primitive of every mesh node, in world space.
+    pub objects: Vec<Box<dyn Object>>,
+    /// The triangles of the emissive primitives, for sampling directly.
+    pub lights: Vec<Box<dyn PdfObject>>,
+    /// The first camera of the file, if it is a perspective camera placed in
+    /// the scene.
+    pub camera: Option<Camera>,
+}
+
+/// Loads the default scene of the glTF file at `path`, or its first scene if
+/// there is no default.
+///
+/// Node transforms are baked into the mesh vertices. Metallic-roughness
+/// materials are mapped onto the closest `Material`:
+///
+/// - emissive ones become `DiffuseLight`;
+/// - transmissive ones (`KHR_materials_transmission`) become `Dielectric`
+///   with their `KHR_materials_ior` index;
+/// - mostly metallic ones become `Metal`, with the base color as albedo and
+///   the roughness as fuzz;
+/// - the rest become `Lambertian` with the base color and texture as albedo.
+///
+/// Textures wrap as their samplers say, repeating by default.
+///
+/// The camera is given the `aspect` ratio of the image to render, whatever
+/// the file says.
+pub fn load_gltf<P: AsRef<Path>>(path: P, aspect: f64) -> Result<GltfScene, LoadError> {
+    let (document, buffers, images) = gltf::import(path).map_err(import_error)?;
+    convert(&document, &buffers, &images, aspect)
+}
+
+fn import_error(e: gltf::Error) -> LoadError {
+    match e {
+        gltf::Error::Io(e) => LoadError::Io(e),
+        gltf::Error::Deserialize(e) => {
+            // The message ends with the position, which is reported separately.
+            let mut message = e.to_string();
+            message.truncate(message.rfind(" at line ").unwrap_or(message.len()));
+            LoadError::parse(
+                Position::LineColumn {
+                    line: e.line(),
+                    column: e.column(),
+                },
+                message,
+            )
+        }
+        e => LoadError::Invalid(e.to_string()),
+    }
+}
+
+/// Converts an imported glTF file; see `load_gltf`.
+fn convert(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    images: &[Data],
+    aspect: f64,
+) -> Result<GltfScene, LoadError> {
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or_else(|| LoadError::Invalid("file has no scenes".to_string()))?;
+
+    let mut loader = Loader {
+        buffers,
+        images,
+        aspect,
+        materials: HashMap::new(),
+        textures: HashMap::new(),
+        objects: vec![],
+        lights: vec![],
+        camera: None,
+    };
+    for node in scene.nodes() {
+        loader.node(&node, IDENTITY)?;
+    }
+
+    Ok(GltfScene {
+        objects: loader.objects,
+        lights: loader.lights,
+        camera: loader.camera,
+    })
+}
+
+/// A column-major affine transform, as stored by glTF.
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.; 4]; 4];
+    for (column, b) in m.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[k]).sum();
+        }
+    }
+    m
+}
+
+/// The `i`th column of the linear part of `m`.
+fn column(m: &Matrix, i: usize) -> Vec3 {
+    Vec3(m[i][0], m[i][1], m[i][2])
+}
+
+fn transform_vector(m: &Matrix, v: Vec3) -> Vec3 {
+    v.0 * column(m, 0) + v.1 * column(m, 1) + v.2 * column(m, 2)
+}
+
+fn transform_point(m: &Matrix, p: Vec3) -> Vec3 {
+    transform_vector(m, p) + column(m, 3)
+}
+
+/// Transforms a normal by the inverse transpose of the linear part of `m`,
+/// up to a positive scale.
+fn transform_normal(m: &Matrix, n: Vec3) -> Vec3 {
+    let (c0, c1, c2) = (column(m, 0), column(m, 1), column(m, 2));
+    let sign = c0.dot(c1.cross(&c2)).signum();
+    (sign * (n.0 * c1.cross(&c2) + n.1 * c2.cross(&c0) + n.2 * c0.cross(&c1))).into_unit()
+}
+
+/// State kept while walking the node hierarchy.
+struct Loader<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [Data],
+    aspect: f64,
+    /// Converted materials by index, `None` being the default material.
+    materials: HashMap<Option<usize>, Material>,
+    /// Converted textures by image index and wrapping.
+    textures: HashMap<(usize, [Wrap; 2]), Texture>,
+    objects: Vec<Box<dyn Object>>,
+    lights: Vec<Box<dyn PdfObject>>,
+    camera: Option<Camera>,
+}
+
+impl Loader<'_> {
+    fn node(&mut self, node: &gltf::Node, parent: Matrix) -> Result<(), LoadError> {
+        let local = node.transform().matrix().map(|c| c.map(f64::from));
+        let world = multiply(&parent, &local);
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if let Some(data) = self.primitive(&primitive, &world).map_err(|message| {
+                    LoadError::Invalid(format!(
+                        "mesh {} primitive {}: {}",
+                        mesh.index(),
+                        primitive.index(),
+                        message
+                    ))
+                })? {
+                    let material = self.material(&primitive.material());
+                    let mesh = Mesh::new(data, material.clone())?;
+                    if material.is_emitter() {
+                        self.lights.extend(
+                            mesh.data()
+                                .triangles(&material)
+                                .map(|triangle| Box::new(triangle) as Box<dyn PdfObject>),
+                        );
+                    }
+                    self.objects.push(Box::new(mesh));
+                }
+            }
+        }
+
+        if let Some(camera) = node.camera() {
+            if let (0, Projection::Perspective(perspective), None) =
+                (camera.index(), camera.projection(), &self.camera)
+            {
+                // glTF cameras look down -Z with +Y up.
+                let origin = transform_point(&world, Vec3(0., 0., 0.));
+                let forward = transform_vector(&world, Vec3(0., 0., -1.));
+                let up = transform_vector(&world, Vec3(0., 1., 0.));
+                self.camera = Some(Camera::look(
+                    origin,
+                    origin + forward,
+                    up,
+                    f64::from(perspective.yfov()).to_degrees(),
+                    self.aspect,
+                    0.,
+                    1.,
+                    0. ..1.,
+                ));
+            }
+        }
+
+        for child in node.children() {
+            self.node(&child, world)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the triangles of a primitive into world space, or `None` if it
+    /// has no triangles.
+    fn primitive(
+        &self,
+        primitive: &gltf::Primitive,
+        world: &Matrix,
+    ) -> Result<Option<MeshData>, String> {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+        let positions: Vec<Vec3> = reader
+            .read_positions()
+            .ok_or("no POSITION attribute")?
+            .map(|[x, y, z]| transform_point(world, Vec3(x.into(), y.into(), z.into())))
+            .collect();
+        let normals: Vec<Vec3> = reader
+            .read_normals()
+            .map(|normals| {
+                normals
+                    .map(|[x, y, z]| transform_normal(world, Vec3(x.into(), y.into(), z.into())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let tex_coord = primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .map_or(0, |info| info.tex_coord());
+        let uvs: Vec<(f64, f64)> = reader
+            .read_tex_coords(tex_coord)
+            // glTF puts the origin of texture space at the top left.
+            .map(|uvs| {
+                uvs.into_f32()
+                    .map(|[u, v]| (u.into(), 1. - f64::from(v)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let corners: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        let mut indices: Vec<[usize; 3]> = match primitive.mode() {
+            Mode::Triangles => corners
+                .chunks_exact(3)
+                .map(|c| [c[0], c[1], c[2]])
+                .collect(),
+            Mode::TriangleStrip => (2..corners.len())
+                .map(|i| match i % 2 {
+                    0 => [corners[i - 2], corners[i - 1], corners[i]],
+                    _ => [corners[i - 1], corners[i - 2], corners[i]],
+                })
+                .collect(),
+            Mode::TriangleFan => (2..corners.len())
+                .map(|i| [corners[0], corners[i - 1], corners[i]])
+                .collect(),
+            // Points and lines have no surface to hit.
+            _ => return Ok(None),
+        };
+        if indices.is_empty() {
+            return Ok(None);
+        }
+        if indices.iter().flatten().any(|&i| i >= positions.len()) {
+            return Err("vertex index out of range".to_string());
+        }
+        if normals.len() != positions.len() && !normals.is_empty()
+            || uvs.len() != positions.len() && !uvs.is_empty()
+        {
+            return Err("attributes differ in length".to_string());
+        }
+        let finite = |i: usize| {
+            positions[i].is_finite()
+                && normals.get(i).iter().all(|n| n.is_finite())
+                && uvs
+                    .get(i)
+                    .iter()
+                    .all(|(u, v)| u.is_finite() && v.is_finite())
+        };
+        if let Some(i) = (0..positions.len()).find(|&i| !finite(i)) {
+            return Err(format!("vertex {} has a value that isn't finite", i));
+        }
+        // A mirroring transform turns the winding order around.
+        let (c0, c1, c2) = (column(world, 0), column(world, 1), column(world, 2));
+        if c0.dot(c1.cross(&c2)) < 0. {
+            for face in &mut indices {
+                face.swap(1, 2);
+            }
+        }
+
+        Ok(Some(MeshData {
+            positions,
+            normals,
+            uvs,
+            indices,
+        }))
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Material {
+        if let Some(converted) = self.materials.get(&material.index()) {
+            return converted.clone();
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _] = pbr.base_color_factor().map(f64::from);
+        let base_color = Vec3(r, g, b);
+        let [r, g, b] = material.emissive_factor().map(f64::from);
+        let emissive = Vec3(r, g, b) * f64::from(material.emissive_strength().unwrap_or(1.));
+        let transmission = material
+            .transmission()
+            .map_or(0., |t| t.transmission_factor());
+
+        let converted = if emissive.reduce(f64::max) > 0. {
+            Material::DiffuseLight {
+                emission: self.textured(material.emissive_texture(), emissive),
+                brightness: 1.,
+            }
+        } else if transmission > 0.5 {
+            // Volume attenuation gives the color left after a distance.
+            let absorption = material.volume().map_or(Vec3::default(), |volume| {
+                let [r, g, b] = volume.attenuation_color().map(f64::from);
+                let distance = f64::from(volume.attenuation_distance());
+                Vec3(r, g, b).map(|c| -c.max(1e-6).ln() / distance)
+            });
+            Material::Dielectric {
+                ref_idx: material.ior().map_or(1.5, f64::from),
+                absorption,
+            }
+        } else if pbr.metallic_factor() > 0.5 {
+            Material::Metal {
+                albedo: base_color,
+                fuzz: f64::from(pbr.roughness_factor()).clamp(0., 1.),
+            }
+        } else {
+            Material::Lambertian {
+                albedo: self.textured(pbr.base_color_texture(), base_color),
+            }
+        };
+        self.materials.insert(material.index(), converted.clone());
+        converted
+    }
+
+    /// The sRGB encoded `info` texture scaled by `factor`, or just `factor`.
+    fn textured(&mut self, info: Option<gltf::texture::Info>, factor: Vec3) -> Texture {
+        match info {
+            Some(info) => Texture::Scaled {
+                factor,
+                texture: Box::new(self.texture(&info.texture())),
+            },
+            None => texture::constant(factor),
+        }
+    }
+
+    fn texture(&mut self, texture: &gltf::Texture) -> Texture {
+        let wrap = |mode| match mode {
+            WrappingMode::ClampToEdge => Wrap::Clamp,
+            WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+            WrappingMode::Repeat => Wrap::Repeat,
+        };
+        let sampler = texture.sampler();
+        let wrap = [wrap(sampler.wrap_s()), wrap(sampler.wrap_t())];
+        let index = texture.source().index();
+        let images = self.images;
+        self.textures
+            .entry((index, wrap))
+            .or_insert_with(|| texture::texels(texels(&images[index]), wrap))
+            .clone()
+    }
+}
+
+/// Converts decoded image data to texels, linearising sRGB encoded 8 and 16 bit
+/// channels.
+fn texels(image: &Data) -> Texels {
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |b: &[u8]| match bytes {
+        1 => f64::from(b[0]) / 255.,
+        2 => f64::from(u16::from_ne_bytes([b[0], b[1]])) / 65535.,
+        _ => f64::from(f32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
+    };
+    let decode = |x: f64| if bytes < 4 { srgb_decode(x) } else { x };
+
+    let data = image
+        .pixels
+        .chunks_exact(channels * bytes)
+        .map(|texel| {
+            let value = |i: usize| decode(channel(&texel[i * bytes..]));
+            match channels {
+                1 | 2 => Vec3::from(value(0)),
+                _ => Vec3(value(0), value(1), value(2)),
+            }
+        })
+        .collect();
+    Texels {
+        width: image.width as usize,
+        height: image.height as usize,
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_file::{MaterialDesc, ObjectDesc};
+
+    /// A binary glTF file with the given JSON and binary chunks.
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().div_ceil(4) * 4, b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().div_ceil(4) * 4, 0);
+
+        let mut bytes = b"glTF".to_vec();
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"JSON");
+        bytes.extend_from_slice(&json);
+        bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"BIN\0");
+        bytes.extend_from_slice(&bin);
+        bytes
+    }
+
+    /// A triangle drawn with an emissive material and with two textured ones,
+    /// the first with a sampler that mirrors in `u` and clamps in `v`, and a
+    /// camera looking at it.
+    fn scene() -> Vec<u8> {
+        let mut bin = vec![];
+        for x in &[0f32, 0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0., 0., 1.] {
+            bin.extend_from_slice(&x.to_le_bytes());
+        }
+        let mut png = vec![];
+        image::DynamicImage::ImageRgb8(
+            image::RgbImage::from_raw(2, 1, vec![0, 0, 0, 255, 255, 255]).unwrap(),
+        )
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .unwrap();
+        bin.extend_from_slice(&png);
+
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scene": 0,
+            "scenes": [{"nodes": [0, 1]}],
+            "nodes": [
+                {"mesh": 0, "translation": [0, 0, 2]},
+                {"camera": 0, "translation": [0, 0, 5]}
+            ],
+            "cameras": [{"type": "perspective", "perspective": {"yfov": 0.8, "znear": 0.1}}],
+            "meshes": [{"primitives": [
+                {"attributes": {"POSITION": 0, "TEXCOORD_0": 1}, "material": 0},
+                {"attributes": {"POSITION": 0, "TEXCOORD_0": 1}, "material": 1},
+                {"attributes": {"POSITION": 0, "TEXCOORD_0": 1}, "material": 2}
+            ]}],
+            "materials": [
+                {"emissiveFactor": [1, 0.5, 0.25]},
+                {"pbrMetallicRoughness": {"baseColorTexture": {"index": 0}, "metallicFactor": 0}},
+                {"pbrMetallicRoughness": {"baseColorTexture": {"index": 1}, "metallicFactor": 0}}
+            ],
+            "textures": [{"source": 0, "sampler": 0}, {"source": 0}],
+            "samplers": [{"wrapS": 33648, "wrapT": 33071}],
+            "images": [{"bufferView": 2, "mimeType": "image/png"}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                 "min": [0, 0, 0], "max": [1, 1, 0]},
+                {"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}
+            ],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 24},
+                {"buffer": 0, "byteOffset": 60, "byteLength": PNG}
+            ],
+            "buffers": [{"byteLength": BIN}]
+        }"#
+        .replace("PNG", &png.len().to_string())
+        .replace("BIN", &bin.len().to_string());
+        glb(&json, &bin)
+    }
+
+    fn load(bytes: &[u8]) -> Result<GltfScene, LoadError> {
+        let (document, buffers, images) = gltf::import_slice(bytes).map_err(import_error)?;
+        convert(&document, &buffers, &images, 1.)
+    }
+
+    #[test]
+    fn meshes_lights_and_camera() {
+        let scene = load(&scene()).unwrap();
+        assert_eq!(scene.objects.len(), 3);
+        assert_eq!(scene.lights.len(), 1);
+        assert!(scene.camera.is_some());
+        match scene.objects[0].describe() {
+            Some(ObjectDesc::Mesh {
+                positions,
+                uvs,
+                material,
+                ..
+            }) => {
+                // Moved by the node, with `v` flipped.
+                assert_eq!(positions[1], Vec3(1., 0., 2.));
+                assert_eq!(uvs[2], (0., 0.));
+                assert!(matches!(material, MaterialDesc::DiffuseLight { .. }));
+            }
+            other => panic!("expected a mesh, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sampler_wrap_modes() {
+        let (document, buffers, images) = gltf::import_slice(scene()).unwrap();
+        let mut loader = Loader {
+            buffers: &buffers,
+            images: &images,
+            aspect: 1.,
+            materials: HashMap::new(),
+            textures: HashMap::new(),
+            objects: vec![],
+            lights: vec![],
+            camera: None,
+        };
+        let wrap = |material: Material| match material {
+            Material::Lambertian {
+                albedo: Texture::Scaled { texture, .. },
+            } => match *texture {
+                Texture::Image { wrap, .. } => wrap,
+                other => panic!("expected an image, got {:?}", other),
+            },
+            other => panic!("expected a textured material, got {:?}", other),
+        };
+        let materials: Vec<_> = document.materials().collect();
+        assert_eq!(
+            wrap(loader.material(&materials[1])),
+            [Wrap::MirroredRepeat, Wrap::Clamp]
+        );
+        assert_eq!(wrap(loader.material(&materials[2])), [Wrap::Repeat; 2]);
+    }
+
+    #[test]
+    fn syntax_error_position() {
+        match load(&glb("{\n  \"asset\": ,\n}", &[])) {
+            Err(LoadError::Parse {
+                position: Position::LineColumn { line, .. },
+                ..
+            }) => assert_eq!(line, 2),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let mut bin = vec![];
+        for x in &[0f32, 0., 0., 1., 0., 0., 0., 1., 0.] {
+            bin.extend_from_slice(&x.to_le_bytes());
+        }
+        for i in &[0u16, 1, 5] {
+            bin.extend_from_slice(&i.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                 "min": [0, 0, 0], "max": [1, 1, 0]},
+                {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+            ],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+            ],
+            "buffers": [{"byteLength": 42}]
+        }"#;
+        assert!(matches!(load(&glb(json, &bin)), Err(LoadError::Invalid(_))));
+    }
+
+    #[test]
+    fn non_finite_position() {
+        let mut bin = vec![];
+        for x in &[0f32, 0., 0., 1., 0., 0., 0., f32::NAN, 0.] {
+            bin.extend_from_slice(&x.to_le_bytes());
+        }
+        let json = r#"{
+            "asset": {"version": "2.0"},
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0}],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}}]}],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                 "min": [0, 0, 0], "max": [1, 1, 0]}
+            ],
+            "bufferViews": [{"buffer": 0, "byteOffset": 0, "byteLength": 36}],
+            "buffers": [{"byteLength": 36}]
+        }"#;
+        match load(&glb(json, &bin)) {
+            Err(LoadError::Invalid(message)) => {
+                assert!(message.contains("vertex 2"), "{}", message)
+            }
+            Err(e) => panic!("expected an invalid mesh, got {}", e),
+            Ok(_) => panic!("expected an invalid mesh"),
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = scene();
+        for len in 0..bytes.len() {
+            assert!(load(&bytes[..len]).is_err(), "read {} bytes", len);
+        }
+    }
+}
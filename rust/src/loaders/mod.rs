@@ -0,0 +1,106 @@
+//! Importers turning files from other tools into scene objects.
+
+mod gltf;
+mod obj;
+mod ply;
+mod stl;
+
+pub use self::gltf::{load_gltf, GltfScene};
+pub use obj::load_obj;
+pub use ply::load_ply;
+pub use stl::load_stl;
+
+use std::fmt;
+
+/// Where in a file a problem was found.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Position {
+    /// A 1-based line of a text file.
+    Line(usize),
+    /// A 1-based line and column of a text file.
+    LineColumn { line: usize, column: usize },
+    /// A byte offset into a binary file.
+    Byte(usize),
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Position::Line(line) => write!(f, "line {}", line),
+            Position::LineColumn { line, column } => {
+                write!(f, "line {}, column {}", line, column)
+            }
+            Position::Byte(offset) => write!(f, "byte {}", offset),
+        }
+    }
+}
+
+/// An error loading a file.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file, or one it refers to, could not be read.
+    Io(std::io::Error),
+    /// The file is malformed, or the part of it at `position` describes
+    /// something that can't be rendered.
+    Parse { position: Position, message: String },
+    /// The file is well-formed, but describes something that can't be
+    /// rendered.
+    Invalid(String),
+}
+
+impl LoadError {
+    pub(crate) fn parse(position: Position, message: impl Into<String>) -> Self {
+        LoadError::Parse {
+            position,
+            message: message.into(),
+        }
+    }
+
+    /// Names the file a parse error was found in, for errors in files
+    /// referenced by the one being loaded.
+    pub(crate) fn in_file(self, path: &std::path::Path) -> Self {
+        match self {
+            LoadError::Parse { position, message } => {
+                LoadError::parse(position, format!("{}: {}", path.display(), message))
+            }
+            LoadError::Invalid(message) => {
+                LoadError::Invalid(format!("{}: {}", path.display(), message))
+            }
+            LoadError::Io(e) => LoadError::Invalid(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Places an `Invalid` error at `position` in the file being loaded, if
+    /// it is known.
+    pub(crate) fn at(self, position: Option<Position>) -> Self {
+        match (self, position) {
+            (LoadError::Invalid(message), Some(position)) => LoadError::parse(position, message),
+            (e, _) => e,
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{}", e),
+            LoadError::Parse { position, message } => write!(f, "{}: {}", position, message),
+            LoadError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Parse { .. } | LoadError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
@@ -0,0 +1,534 @@
+//! Loader for [Wavefront OBJ][obj] meshes and their MTL material libraries.
+//!
+//! [obj]: https://en.wikipedia.org/wiki/Wavefront_.obj_file
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    loaders::{LoadError, Position},
+    objects::{Mesh, MeshData, Object},
+    texture::{self, Texture},
+    vec3::Vec3,
+    Material,
+};
+
+/// Loads the OBJ file at `path`, with the materials of any MTL libraries it
+/// references.
+///
+/// Faces are grouped into one `Mesh` per group (`g` or `o`) and material, and
+/// polygons with more than three corners are split into triangle fans. MTL
+/// materials are mapped onto the closest `Material`:
+///
+/// - emissive ones (`Ke`) become `DiffuseLight`;
+/// - transparent ones (`d` below 1, or `Tr` above 0) become `Dielectric` with
+///   refractive index `Ni`;
+/// - mirror-like ones (`illum` 3, or `Ks` brighter than `Kd`) become `Metal`
+///   with albedo `Ks`, fuzzier for lower `Ns`;
+/// - the rest become `Lambertian` with albedo `Kd` or `map_Kd`.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Box<dyn Object>>, LoadError> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    parse_obj(&text, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// One corner of a face, as indices into the vertex attribute lists.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Faces sharing a group and material.
+struct Batch {
+    group: String,
+    material: Option<String>,
+    faces: Vec<[Corner; 3]>,
+}
+
+/// Parses OBJ `text`, resolving material libraries relative to `dir`.
+fn parse_obj(text: &str, dir: &Path) -> Result<Vec<Box<dyn Object>>, LoadError> {
+    let mut positions = vec![];
+    let mut uvs = vec![];
+    let mut normals = vec![];
+    let mut materials = HashMap::new();
+    let mut batches = vec![Batch {
+        group: String::new(),
+        material: None,
+        faces: vec![],
+    }];
+
+    for (n, line) in text.lines().enumerate() {
+        let position = Position::Line(n + 1);
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let v = floats(&args, 3, position)?;
+                positions.push(Vec3(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = floats(&args, 1, position)?;
+                uvs.push((v[0], v.get(1).copied().unwrap_or(0.)));
+            }
+            "vn" => {
+                let v = floats(&args, 3, position)?;
+                normals.push(Vec3(v[0], v[1], v[2]));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(LoadError::parse(position, "face with fewer than 3 corners"));
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| corner(arg, (positions.len(), uvs.len(), normals.len()), position))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let faces = &mut batches.last_mut().unwrap().faces;
+                for i in 1..corners.len() - 1 {
+                    faces.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let group = args.join(" ");
+                let material = batches.last().unwrap().material.clone();
+                start_batch(&mut batches, group, material);
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                if !materials.contains_key(&name) {
+                    return Err(LoadError::parse(
+                        position,
+                        format!("unknown material {:?}", name),
+                    ));
+                }
+                let group = batches.last().unwrap().group.clone();
+                start_batch(&mut batches, group, Some(name));
+            }
+            "mtllib" => {
+                for library in &args {
+                    let path = dir.join(library);
+                    let text = std::fs::read_to_string(&path)?;
+                    let base = path.parent().unwrap_or(dir);
+                    materials.extend(parse_mtl(&text, base).map_err(|e| e.in_file(&path))?);
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are
+            // ignored.
+            _ => {}
+        }
+    }
+
+    let default = Material::Lambertian {
+        albedo: texture::constant(Vec3::from(0.8)),
+    };
+
+    batches
+        .into_iter()
+        .filter(|batch| !batch.faces.is_empty())
+        .map(|batch| {
+            let material = match &batch.material {
+                Some(name) => materials[name].clone(),
+                None => default.clone(),
+            };
+            let mesh = Mesh::new(
+                mesh_data(&batch.faces, &positions, &uvs, &normals),
+                material,
+            )?;
+            Ok(Box::new(mesh) as Box<dyn Object>)
+        })
+        .collect()
+}
+
+/// Starts collecting faces into a new batch, unless the current one is still
+/// empty and can be reused.
+fn start_batch(batches: &mut Vec<Batch>, group: String, material: Option<String>) {
+    let last = batches.last_mut().unwrap();
+    if last.faces.is_empty() {
+        last.group = group;
+        last.material = material;
+    } else {
+        batches.push(Batch {
+            group,
+            material,
+            faces: vec![],
+        });
+    }
+}
+
+/// Builds vertex buffers for `faces`, with one vertex per distinct combination
+/// of attributes. Normals and texture coordinates are only kept if every
+/// corner has them.
+fn mesh_data(
+    faces: &[[Corner; 3]],
+    positions: &[Vec3],
+    uvs: &[(f64, f64)],
+    normals: &[Vec3],
+) -> MeshData {
+    let corners = || faces.iter().flatten();
+    let has_uvs = corners().all(|c| c.uv.is_some());
+    let has_normals = corners().all(|c| c.normal.is_some());
+
+    let mut data = MeshData::default();
+    let mut vertices = HashMap::new();
+    for face in faces {
+        let mut indices = [0; 3];
+        for (index, corner) in indices.iter_mut().zip(face) {
+            let corner = Corner {
+                uv: corner.uv.filter(|_| has_uvs),
+                normal: corner.normal.filter(|_| has_normals),
+                ..*corner
+            };
+            *index = *vertices.entry(corner).or_insert_with(|| {
+                data.positions.push(positions[corner.position]);
+                if let Some(uv) = corner.uv {
+                    data.uvs.push(uvs[uv]);
+                }
+                if let Some(normal) = corner.normal {
+                    data.normals.push(normals[normal]);
+                }
+                data.positions.len() - 1
+            });
+        }
+        data.indices.push(indices);
+    }
+    data
+}
+
+/// Parses a face corner like `1`, `1/2`, `1//3` or `1/2/3`, given the number
+/// of positions, texture coordinates and normals defined so far.
+fn corner(
+    arg: &str,
+    (positions, uvs, normals): (usize, usize, usize),
+    position: Position,
+) -> Result<Corner, LoadError> {
+    let mut parts = arg.split('/');
+    let mut index = |count: usize, required: bool| -> Result<Option<usize>, LoadError> {
+        match parts.next() {
+            Some(part) if !part.is_empty() => {
+                let i: i64 = part
+                    .parse()
+                    .map_err(|_| LoadError::parse(position, format!("bad index {:?}", part)))?;
+                // Negative indices count back from the latest element.
+                let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+                if i == 0 || resolved < 0 || resolved >= count as i64 {
+                    return Err(LoadError::parse(
+                        position,
+                        format!("index {} out of range", i),
+                    ));
+                }
+                Ok(Some(resolved as usize))
+            }
+            _ if required => Err(LoadError::parse(
+                position,
+                format!("face corner {:?} has no position", arg),
+            )),
+            _ => Ok(None),
+        }
+    };
+
+    Ok(Corner {
+        position: index(positions, true)?.unwrap(),
+        uv: index(uvs, false)?,
+        normal: index(normals, false)?,
+    })
+}
+
+/// Parses at least `min` finite floating point arguments.
+fn floats(args: &[&str], min: usize, position: Position) -> Result<Vec<f64>, LoadError> {
+    if args.len() < min {
+        return Err(LoadError::parse(
+            position,
+            format!("expected {} numbers, found {}", min, args.len()),
+        ));
+    }
+    args.iter()
+        .map(|arg| match arg.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(x),
+            Ok(_) => Err(LoadError::parse(
+                position,
+                format!("number {:?} isn't finite", arg),
+            )),
+            Err(_) => Err(LoadError::parse(position, format!("bad number {:?}", arg))),
+        })
+        .collect()
+}
+
+/// Statements of one material in an MTL library.
+#[derive(Default)]
+struct MtlParams {
+    kd: Option<Vec3>,
+    ks: Option<Vec3>,
+    ke: Option<Vec3>,
+    ns: Option<f64>,
+    ni: Option<f64>,
+    dissolve: Option<f64>,
+    illum: Option<u32>,
+    map_kd: Option<Texture>,
+    map_ke: Option<Texture>,
+}
+
+impl MtlParams {
+    fn into_material(self) -> Material {
+        let kd = self.kd.unwrap_or_else(|| Vec3::from(0.8));
+        let ks = self.ks.unwrap_or_default();
+        let ke = self.ke.unwrap_or_default();
+
+        if ke.reduce(f64::max) > 0. || self.map_ke.is_some() {
+            Material::DiffuseLight {
+                emission: self.map_ke.unwrap_or_else(|| texture::constant(ke)),
+                brightness: 1.,
+            }
+        } else if self.dissolve.is_some_and(|d| d < 1.) || matches!(self.illum, Some(4..=7)) {
+            Material::Dielectric {
+                ref_idx: self.ni.unwrap_or(1.5),
+                absorption: Vec3::default(),
+            }
+        } else if self.illum == Some(3) || ks.luminance() > kd.luminance() {
+            // Blinn-Phong exponent to roughness, after Walter et al.
+            let roughness = (2. / (self.ns.unwrap_or(0.) + 2.)).sqrt();
+            Material::Metal {
+                albedo: ks,
+                fuzz: roughness.clamp(0., 1.),
+            }
+        } else {
+            Material::Lambertian {
+                albedo: self.map_kd.unwrap_or_else(|| texture::constant(kd)),
+            }
+        }
+    }
+}
+
+/// Parses MTL `text`, resolving texture maps relative to `dir`.
+fn parse_mtl(text: &str, dir: &Path) -> Result<HashMap<String, Material>, LoadError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlParams)> = None;
+
+    for (n, line) in text.lines().enumerate() {
+        let position = Position::Line(n + 1);
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, params)) = current.take() {
+                materials.insert(name, params.into_material());
+            }
+            current = Some((args.join(" "), MtlParams::default()));
+            continue;
+        }
+
+        let params = match &mut current {
+            Some((_, params)) => params,
+            None => {
+                return Err(LoadError::parse(
+                    position,
+                    format!("{} before any newmtl", keyword),
+                ))
+            }
+        };
+        let color = |args: &[&str]| {
+            let v = floats(args, 1, position)?;
+            // A single value is a gray level.
+            Ok::<_, LoadError>(match v[..] {
+                [r, g, b, ..] => Vec3(r, g, b),
+                _ => Vec3::from(v[0]),
+            })
+        };
+        let map = |args: &[&str]| {
+            // Options like `-s 1 1 1` come first; the file name is last.
+            let file = args
+                .last()
+                .ok_or_else(|| LoadError::parse(position, "texture map without a file"))?;
+            texture::image_texture(dir.join(file))
+                .map_err(|e| LoadError::parse(position, format!("{}: {}", file, e)))
+        };
+
+        match keyword {
+            "Kd" => params.kd = Some(color(&args)?),
+            "Ks" => params.ks = Some(color(&args)?),
+            "Ke" => params.ke = Some(color(&args)?),
+            "Ns" => params.ns = Some(floats(&args, 1, position)?[0]),
+            "Ni" => params.ni = Some(floats(&args, 1, position)?[0]),
+            "d" => params.dissolve = Some(floats(&args, 1, position)?[0]),
+            "Tr" => params.dissolve = Some(1. - floats(&args, 1, position)?[0]),
+            "illum" => params.illum = Some(floats(&args, 1, position)?[0] as u32),
+            "map_Kd" => params.map_kd = Some(map(&args)?),
+            "map_Ke" => params.map_ke = Some(map(&args)?),
+            _ => {}
+        }
+    }
+
+    if let Some((name, params)) = current {
+        materials.insert(name, params.into_material());
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_file::{MaterialDesc, ObjectDesc};
+
+    fn parse(text: &str) -> Result<Vec<Box<dyn Object>>, LoadError> {
+        parse_obj(text, Path::new(""))
+    }
+
+    /// Positions and indices of a loaded mesh.
+    fn mesh(object: &dyn Object) -> (Vec<Vec3>, Vec<[usize; 3]>, MaterialDesc) {
+        match object.describe() {
+            Some(ObjectDesc::Mesh {
+                positions,
+                indices,
+                material,
+                ..
+            }) => (positions, indices, material),
+            other => panic!("expected a mesh, got {:?}", other),
+        }
+    }
+
+    fn error_line(result: Result<Vec<Box<dyn Object>>, LoadError>) -> usize {
+        match result {
+            Err(LoadError::Parse {
+                position: Position::Line(line),
+                ..
+            }) => line,
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn quad_becomes_two_triangles() {
+        let objects = parse(
+            "# A unit square.\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             f 1 2 3 4\n",
+        )
+        .unwrap();
+        assert_eq!(objects.len(), 1);
+        let (positions, indices, _) = mesh(&*objects[0]);
+        assert_eq!(positions.len(), 4);
+        assert_eq!(indices, [[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn negative_indices_and_attributes() {
+        let objects = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 0\nvt 0 1\n\
+             vn 0 0 1\n\
+             f -3/-3/-1 -2/-2/-1 -1/-1/-1\n",
+        )
+        .unwrap();
+        match objects[0].describe() {
+            Some(ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                ..
+            }) => {
+                assert_eq!(
+                    positions,
+                    [Vec3(0., 0., 0.), Vec3(1., 0., 0.), Vec3(0., 1., 0.)]
+                );
+                assert_eq!(normals, [Vec3(0., 0., 1.); 3]);
+                assert_eq!(uvs, [(0., 0.), (1., 0.), (0., 1.)]);
+            }
+            other => panic!("expected a mesh, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn groups_split_meshes() {
+        let objects = parse(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\n\
+             g first\nf 1 2 3\n\
+             g second\nf 2 4 3\n",
+        )
+        .unwrap();
+        assert_eq!(objects.len(), 2);
+        // Vertices are only kept by the meshes that use them.
+        assert_eq!(mesh(&*objects[1]).0.len(), 3);
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(error_line(parse("v 0 0\n")), 1);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 x 0\n")), 2);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nf 1 2\n")), 3);
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n")), 3);
+        assert_eq!(error_line(parse("v 0 0 0\nf 0 1 1\n")), 2);
+        assert_eq!(error_line(parse("v 0 0 0\nf 1/1 1 1\n")), 2);
+        assert_eq!(error_line(parse("v 0 0 0\nf /1 1 1\n")), 2);
+        assert_eq!(error_line(parse("usemtl missing\n")), 1);
+        // Non-finite numbers would break the mesh's BVH.
+        assert_eq!(error_line(parse("v 0 0 0\nv nan nan nan\n")), 2);
+        assert_eq!(error_line(parse("vn 0 0 1\nvn inf 0 0\n")), 2);
+        assert_eq!(error_line(parse("vt -inf 0\n")), 1);
+    }
+
+    #[test]
+    fn truncated() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvn 0 0 1\nf 1/1/1 2/1/1 3/1/1\n";
+        for len in 0..text.len() {
+            // Any outcome but a panic will do.
+            let _ = parse(&text[..len]);
+        }
+    }
+
+    #[test]
+    fn mtl_materials() {
+        let materials = parse_mtl(
+            "newmtl matte\nKd 0.5 0.25 0.125\n\
+             newmtl lamp\nKe 4\n\
+             newmtl glass\nd 0.2\nNi 1.33\n\
+             newmtl mirror\nKd 0\nKs 0.9 0.9 0.9\nNs 1000\n",
+            Path::new(""),
+        )
+        .unwrap();
+        let describe = |name: &str| materials[name].describe().unwrap();
+        assert_eq!(
+            describe("matte"),
+            MaterialDesc::Lambertian {
+                albedo: crate::scene_file::TextureDesc::Constant(Vec3(0.5, 0.25, 0.125)),
+            }
+        );
+        assert!(matches!(
+            describe("lamp"),
+            MaterialDesc::DiffuseLight { brightness, .. } if brightness == 1.
+        ));
+        assert!(matches!(
+            describe("glass"),
+            MaterialDesc::Dielectric { ref_idx, .. } if ref_idx == 1.33
+        ));
+        assert!(matches!(
+            describe("mirror"),
+            MaterialDesc::Metal { fuzz, .. } if fuzz < 0.1
+        ));
+    }
+
+    #[test]
+    fn mtl_malformed() {
+        let line = |text: &str| match parse_mtl(text, Path::new("")) {
+            Err(LoadError::Parse {
+                position: Position::Line(line),
+                ..
+            }) => line,
+            _ => panic!("expected a parse error"),
+        };
+        assert_eq!(line("Kd 1 1 1\n"), 1);
+        assert_eq!(line("newmtl a\nKd\n"), 2);
+        assert_eq!(line("newmtl a\nNs high\n"), 2);
+        assert_eq!(line("newmtl a\nmap_Kd missing.png\n"), 2);
+    }
+}
@@ -0,0 +1,521 @@
+//! Loader for [PLY][ply] polygon files, in ASCII or binary little-endian form.
+//!
+//! [ply]: http://paulbourke.net/dataformats/ply/
+
+use std::path::Path;
+
+use crate::{
+    loaders::{LoadError, Position},
+    objects::{Mesh, MeshData},
+    vec3::Vec3,
+    Material,
+};
+
+/// Loads the PLY file at `path` as a mesh with the given material.
+///
+/// Vertex positions, and normals (`nx`, `ny`, `nz`) and texture coordinates
+/// (`u`/`v` or `s`/`t`) when present, are read from the `vertex` element, and
+/// polygons from the `vertex_indices` list of the `face` element, split into
+/// triangle fans. Other elements and properties are skipped.
+pub fn load_ply<P: AsRef<Path>>(path: P, material: Material) -> Result<Mesh, LoadError> {
+    let bytes = std::fs::read(path)?;
+    Mesh::new(parse_ply(&bytes)?, material)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn decode(self, b: &[u8]) -> f64 {
+        match self {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Kind {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn index_of(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| names.contains(&p.name.as_str()))
+    }
+}
+
+/// The values of one element instance, with lists flattened into `lists`.
+#[derive(Default)]
+struct Values {
+    scalars: Vec<f64>,
+    lists: Vec<Vec<f64>>,
+}
+
+/// Reads element values from the body of the file.
+struct Body<'a> {
+    format: Format,
+    bytes: &'a [u8],
+    offset: usize,
+    line: usize,
+}
+
+impl Body<'_> {
+    /// Where the next element starts.
+    fn position(&self) -> Position {
+        match self.format {
+            Format::Ascii => Position::Line(self.line + 1),
+            Format::BinaryLittleEndian => Position::Byte(self.offset),
+        }
+    }
+
+    fn read(&mut self, element: &Element) -> Result<Values, LoadError> {
+        let mut values = Values::default();
+        match self.format {
+            Format::Ascii => {
+                let position = self.position();
+                let line = self.next_line().ok_or_else(|| {
+                    LoadError::parse(position, format!("missing {} data", element.name))
+                })?;
+                let mut tokens = line.split_whitespace();
+                let mut next = || -> Result<f64, LoadError> {
+                    let token = tokens.next().ok_or_else(|| {
+                        LoadError::parse(position, format!("too few values for {}", element.name))
+                    })?;
+                    token
+                        .parse()
+                        .map_err(|_| LoadError::parse(position, format!("bad number {:?}", token)))
+                };
+                for property in &element.properties {
+                    match property.kind {
+                        Kind::Scalar(_) => values.scalars.push(next()?),
+                        Kind::List { .. } => {
+                            let count = next()? as usize;
+                            values.scalars.push(count as f64);
+                            values
+                                .lists
+                                .push((0..count).map(|_| next()).collect::<Result<_, _>>()?);
+                        }
+                    }
+                }
+            }
+            Format::BinaryLittleEndian => {
+                for property in &element.properties {
+                    match property.kind {
+                        Kind::Scalar(scalar) => values.scalars.push(self.binary(scalar)?),
+                        Kind::List { count, item } => {
+                            let count = self.binary(count)? as usize;
+                            values.scalars.push(count as f64);
+                            values.lists.push(
+                                (0..count)
+                                    .map(|_| self.binary(item))
+                                    .collect::<Result<_, _>>()?,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// The next non-blank line of an ASCII body.
+    fn next_line(&mut self) -> Option<&str> {
+        loop {
+            if self.offset >= self.bytes.len() {
+                return None;
+            }
+            let rest = &self.bytes[self.offset..];
+            let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+            self.offset += (end + 1).min(rest.len());
+            self.line += 1;
+            let line = std::str::from_utf8(&rest[..end]).unwrap_or("");
+            if !line.trim().is_empty() {
+                return Some(line);
+            }
+        }
+    }
+
+    fn binary(&mut self, scalar: Scalar) -> Result<f64, LoadError> {
+        let end = self.offset + scalar.size();
+        let bytes = self.bytes.get(self.offset..end).ok_or_else(|| {
+            LoadError::parse(Position::Byte(self.offset), "unexpected end of file")
+        })?;
+        self.offset = end;
+        Ok(scalar.decode(bytes))
+    }
+}
+
+/// Parses the header, returning the elements, the format and the offset and
+/// line number at which the body starts.
+fn parse_header(bytes: &[u8]) -> Result<(Vec<Element>, Format, usize, usize), LoadError> {
+    let mut elements: Vec<Element> = vec![];
+    let mut format = None;
+    let mut offset = 0;
+
+    for line_number in 1.. {
+        let position = Position::Line(line_number);
+        let rest = &bytes[offset..];
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| LoadError::parse(position, "header has no end_header"))?;
+        offset += end + 1;
+        let line = std::str::from_utf8(&rest[..end])
+            .map_err(|_| LoadError::parse(position, "header is not text"))?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens[..] {
+            ["ply"] if line_number == 1 => {}
+            _ if line_number == 1 => return Err(LoadError::parse(position, "not a PLY file")),
+            ["format", name, _] => {
+                format = Some(match name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    _ => {
+                        return Err(LoadError::parse(
+                            position,
+                            format!("unsupported format {}", name),
+                        ))
+                    }
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| LoadError::parse(position, format!("bad count {:?}", count)))?,
+                properties: vec![],
+            }),
+            ["property", ..] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| LoadError::parse(position, "property before any element"))?;
+                let scalar = |name: &str| {
+                    Scalar::parse(name)
+                        .ok_or_else(|| LoadError::parse(position, format!("unknown type {}", name)))
+                };
+                let (kind, name) = match tokens[1..] {
+                    ["list", count, item, name] => (
+                        Kind::List {
+                            count: scalar(count)?,
+                            item: scalar(item)?,
+                        },
+                        name,
+                    ),
+                    [ty, name] => (Kind::Scalar(scalar(ty)?), name),
+                    _ => return Err(LoadError::parse(position, "malformed property")),
+                };
+                element.properties.push(Property {
+                    name: name.to_string(),
+                    kind,
+                });
+            }
+            ["end_header"] => {
+                let format =
+                    format.ok_or_else(|| LoadError::parse(position, "header has no format"))?;
+                return Ok((elements, format, offset, line_number));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => {
+                return Err(LoadError::parse(
+                    position,
+                    format!("unexpected header line {:?}", line.trim()),
+                ))
+            }
+        }
+    }
+    unreachable!()
+}
+
+fn parse_ply(bytes: &[u8]) -> Result<MeshData, LoadError> {
+    let (elements, format, offset, line) = parse_header(bytes)?;
+    let mut body = Body {
+        format,
+        bytes,
+        offset,
+        line,
+    };
+    let mut data = MeshData::default();
+
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let position = ["x", "y", "z"].map(|name| element.index_of(&[name]));
+                let normal = ["nx", "ny", "nz"].map(|name| element.index_of(&[name]));
+                let uv = [
+                    element.index_of(&["u", "s", "texture_u", "texture_s"]),
+                    element.index_of(&["v", "t", "texture_v", "texture_t"]),
+                ];
+                let [x, y, z] = match position {
+                    [Some(x), Some(y), Some(z)] => [x, y, z],
+                    _ => {
+                        return Err(LoadError::parse(
+                            body.position(),
+                            "vertex element has no x, y and z",
+                        ))
+                    }
+                };
+                for _ in 0..element.count {
+                    let position = body.position();
+                    let values = body.read(element)?.scalars;
+                    let finite = [x, y, z]
+                        .iter()
+                        .chain(normal.iter().flatten())
+                        .chain(uv.iter().flatten())
+                        .all(|&i| values[i].is_finite());
+                    if !finite {
+                        return Err(LoadError::parse(
+                            position,
+                            "vertex has a value that isn't finite",
+                        ));
+                    }
+                    data.positions.push(Vec3(values[x], values[y], values[z]));
+                    if let [Some(nx), Some(ny), Some(nz)] = normal {
+                        data.normals.push(Vec3(values[nx], values[ny], values[nz]));
+                    }
+                    if let [Some(u), Some(v)] = uv {
+                        data.uvs.push((values[u], values[v]));
+                    }
+                }
+            }
+            "face" => {
+                let list = element
+                    .properties
+                    .iter()
+                    .filter(|p| matches!(p.kind, Kind::List { .. }))
+                    .position(|p| p.name == "vertex_indices" || p.name == "vertex_index")
+                    .ok_or_else(|| {
+                        LoadError::parse(body.position(), "face element has no vertex_indices")
+                    })?;
+                for _ in 0..element.count {
+                    let position = body.position();
+                    let corners = &body.read(element)?.lists[list];
+                    if corners.len() < 3 {
+                        return Err(LoadError::parse(position, "face with fewer than 3 corners"));
+                    }
+                    let corners = corners
+                        .iter()
+                        .map(|&i| {
+                            if i >= 0. && (i as usize) < data.positions.len() {
+                                Ok(i as usize)
+                            } else {
+                                Err(LoadError::parse(
+                                    position,
+                                    format!("vertex index {} out of range", i),
+                                ))
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for i in 1..corners.len() - 1 {
+                        data.indices.push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+            }
+            _ => {
+                for _ in 0..element.count {
+                    body.read(element)?;
+                }
+            }
+        }
+    }
+
+    if data.indices.is_empty() {
+        return Err(LoadError::parse(body.position(), "file has no faces"));
+    }
+    if data.normals.len() != data.positions.len() && !data.normals.is_empty()
+        || data.uvs.len() != data.positions.len() && !data.uvs.is_empty()
+    {
+        return Err(LoadError::parse(
+            body.position(),
+            "only some vertices have normals or texture coordinates",
+        ));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "ply\n\
+        format ascii 1.0\n\
+        comment A unit square.\n\
+        element vertex 4\n\
+        property float x\n\
+        property float y\n\
+        property float z\n\
+        property float u\n\
+        property float v\n\
+        element face 1\n\
+        property list uchar int vertex_indices\n\
+        end_header\n\
+        0 0 0 0 0\n\
+        1 0 0 1 0\n\
+        1 1 0 1 1\n\
+        0 1 0 0 1\n\
+        4 0 1 2 3\n";
+
+    /// A binary triangle with normals and an extra per-vertex property.
+    fn binary_triangle() -> Vec<u8> {
+        let mut bytes = b"ply\n\
+            format binary_little_endian 1.0\n\
+            element vertex 3\n\
+            property double x\n\
+            property double y\n\
+            property double z\n\
+            property uchar flags\n\
+            property float nx\n\
+            property float ny\n\
+            property float nz\n\
+            element face 1\n\
+            property list uchar uint vertex_indices\n\
+            end_header\n"
+            .to_vec();
+        for p in &[[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]] {
+            for x in p {
+                bytes.extend_from_slice(&f64::to_le_bytes(*x));
+            }
+            bytes.push(7);
+            for n in &[0f32, 0., 1.] {
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+        }
+        bytes.push(3);
+        for i in 0u32..3 {
+            bytes.extend_from_slice(&i.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> Position {
+        match parse_ply(bytes) {
+            Err(LoadError::Parse { position, .. }) => position,
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn ascii() {
+        let data = parse_ply(QUAD.as_bytes()).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.uvs[2], (1., 1.));
+        assert!(data.normals.is_empty());
+        assert_eq!(data.indices, [[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn binary() {
+        let data = parse_ply(&binary_triangle()).unwrap();
+        assert_eq!(
+            data.positions,
+            [Vec3(0., 0., 0.), Vec3(1., 0., 0.), Vec3(0., 1., 0.)]
+        );
+        assert_eq!(data.normals, [Vec3(0., 0., 1.); 3]);
+        assert_eq!(data.indices, [[0, 1, 2]]);
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(error(b"obj\nend_header\n"), Position::Line(1));
+        assert_eq!(error(b"ply\nformat ascii 1.0\n"), Position::Line(3));
+        assert_eq!(
+            error(b"ply\nformat binary_big_endian 1.0\nend_header\n"),
+            Position::Line(2)
+        );
+        let bad_type = QUAD.replace("property float u", "property quad u");
+        assert_eq!(error(bad_type.as_bytes()), Position::Line(8));
+        let out_of_range = QUAD.replace("4 0 1 2 3", "3 0 1 4");
+        assert_eq!(error(out_of_range.as_bytes()), Position::Line(17));
+        let bad_number = QUAD.replace("1 1 0 1 1", "1 one 0 1 1");
+        assert_eq!(error(bad_number.as_bytes()), Position::Line(15));
+        let nan = QUAD.replace("1 1 0 1 1", "1 nan 0 1 1");
+        assert_eq!(error(nan.as_bytes()), Position::Line(15));
+        let inf_uv = QUAD.replace("0 1 0 0 1", "0 1 0 0 inf");
+        assert_eq!(error(inf_uv.as_bytes()), Position::Line(16));
+    }
+
+    #[test]
+    fn truncated() {
+        // Only the final newline is optional.
+        let ascii = QUAD.trim_end().as_bytes();
+        let binary = binary_triangle();
+        for bytes in &[ascii, &binary[..]] {
+            for len in 0..bytes.len() {
+                assert!(parse_ply(&bytes[..len]).is_err(), "read {} bytes", len);
+            }
+        }
+    }
+
+    #[test]
+    fn vertices_disagree_on_normals() {
+        let text = "ply\nformat ascii 1.0\n\
+            element vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            element vertex 2\nproperty float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0 0 0 1\n1 0 0\n0 1 0\n3 0 1 2\n";
+        assert!(parse_ply(text.as_bytes()).is_err());
+    }
+}
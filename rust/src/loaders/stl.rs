@@ -0,0 +1,334 @@
+//! Loader for binary and ASCII [STL][stl] files.
+//!
+//! [stl]: https://en.wikipedia.org/wiki/STL_(file_format)
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    loaders::{LoadError, Position},
+    objects::{Mesh, MeshData},
+    vec3::Vec3,
+    Material,
+};
+
+const HEADER: usize = 80;
+const TRIANGLE: usize = 50;
+
+/// Loads the STL file at `path` as a flat shaded mesh with the given
+/// material.
+///
+/// Files starting with `solid` are read as ASCII unless their size matches
+/// the triangle count of a binary header, as some binary exporters start the
+/// header with `solid` too. Corners at identical positions are merged into one
+/// vertex. The stored facet normals are ignored in favour of the winding
+/// order.
+pub fn load_stl<P: AsRef<Path>>(path: P, material: Material) -> Result<Mesh, LoadError> {
+    let bytes = std::fs::read(path)?;
+    Mesh::new(parse_stl(&bytes)?, material)
+}
+
+fn parse_stl(bytes: &[u8]) -> Result<MeshData, LoadError> {
+    let binary_size = bytes
+        .get(HEADER..HEADER + 4)
+        .map(|count| {
+            let count = u32::from_le_bytes([count[0], count[1], count[2], count[3]]) as usize;
+            HEADER + 4 + count * TRIANGLE
+        })
+        .filter(|&size| size == bytes.len());
+    if bytes.starts_with(b"solid") && binary_size.is_none() {
+        parse_ascii(bytes)
+    } else {
+        parse_binary(bytes)
+    }
+}
+
+/// Merges corners at bitwise identical positions into one vertex.
+#[derive(Default)]
+struct Vertices {
+    data: MeshData,
+    indices: HashMap<[u64; 3], usize>,
+}
+
+impl Vertices {
+    fn index(&mut self, position: Vec3) -> usize {
+        let data = &mut self.data;
+        *self
+            .indices
+            .entry([position.0, position.1, position.2].map(f64::to_bits))
+            .or_insert_with(|| {
+                data.positions.push(position);
+                data.positions.len() - 1
+            })
+    }
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<MeshData, LoadError> {
+    if bytes.len() < HEADER + 4 {
+        return Err(LoadError::parse(
+            Position::Byte(bytes.len()),
+            "file is shorter than the STL header",
+        ));
+    }
+    let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+    let expected = HEADER + 4 + count * TRIANGLE;
+    if bytes.len() != expected {
+        return Err(LoadError::parse(
+            Position::Byte(bytes.len().min(expected)),
+            format!(
+                "header promises {} triangles ({} bytes), file has {} bytes",
+                count,
+                expected,
+                bytes.len()
+            ),
+        ));
+    }
+    if count == 0 {
+        return Err(LoadError::parse(
+            Position::Byte(HEADER),
+            "file has no faces",
+        ));
+    }
+
+    let mut vertices = Vertices::default();
+    for (t, triangle) in bytes[HEADER + 4..].chunks_exact(TRIANGLE).enumerate() {
+        let mut face = [0; 3];
+        // The first 12 bytes are the facet normal, the last 2 the attribute
+        // byte count.
+        for (c, (index, corner)) in face
+            .iter_mut()
+            .zip(triangle[12..48].chunks_exact(12))
+            .enumerate()
+        {
+            let [x, y, z] = [0, 4, 8].map(|i| {
+                f32::from_le_bytes([corner[i], corner[i + 1], corner[i + 2], corner[i + 3]]) as f64
+            });
+            let position = Vec3(x, y, z);
+            if !position.is_finite() {
+                return Err(LoadError::parse(
+                    Position::Byte(HEADER + 4 + t * TRIANGLE + 12 * (c + 1)),
+                    "vertex has a coordinate that isn't finite",
+                ));
+            }
+            *index = vertices.index(position);
+        }
+        vertices.data.indices.push(face);
+    }
+    Ok(vertices.data)
+}
+
+/// Reads the `solid`, `facet normal`, `outer loop`, `vertex`, `endloop`,
+/// `endfacet` and `endsolid` statements of an ASCII file. Several solids in
+/// one file go into the same mesh.
+fn parse_ascii(bytes: &[u8]) -> Result<MeshData, LoadError> {
+    let text = std::str::from_utf8(bytes).map_err(|e| {
+        LoadError::parse(
+            Position::Byte(e.valid_up_to()),
+            "ASCII STL file isn't UTF-8",
+        )
+    })?;
+
+    let mut vertices = Vertices::default();
+    // Corners of the facet being read, while inside `outer loop`.
+    let mut corners: Option<Vec<usize>> = None;
+    let mut line = 0;
+    for (i, text) in text.lines().enumerate() {
+        line = i + 1;
+        let position = Position::Line(line);
+        let mut words = text.split_whitespace();
+        match (words.next(), corners.as_mut()) {
+            (None, _) => {}
+            (Some("vertex"), Some(corners)) => {
+                let args: Vec<&str> = words.collect();
+                if args.len() != 3 {
+                    return Err(LoadError::parse(
+                        position,
+                        format!("expected 3 coordinates, found {}", args.len()),
+                    ));
+                }
+                let mut xyz = [0.; 3];
+                for (x, arg) in xyz.iter_mut().zip(&args) {
+                    *x = match arg.parse::<f64>() {
+                        Ok(x) if x.is_finite() => x,
+                        Ok(_) => {
+                            return Err(LoadError::parse(
+                                position,
+                                format!("number {:?} isn't finite", arg),
+                            ))
+                        }
+                        Err(_) => {
+                            return Err(LoadError::parse(position, format!("bad number {:?}", arg)))
+                        }
+                    };
+                }
+                corners.push(vertices.index(Vec3(xyz[0], xyz[1], xyz[2])));
+            }
+            (Some("endloop"), Some(facet)) => {
+                if facet.len() != 3 {
+                    return Err(LoadError::parse(
+                        position,
+                        format!("facet has {} vertices, expected 3", facet.len()),
+                    ));
+                }
+                vertices.data.indices.push([facet[0], facet[1], facet[2]]);
+                corners = None;
+            }
+            (Some("outer"), None) => corners = Some(vec![]),
+            (Some("solid"), None)
+            | (Some("facet"), None)
+            | (Some("endfacet"), None)
+            | (Some("endsolid"), None) => {}
+            (Some(keyword), _) => {
+                return Err(LoadError::parse(
+                    position,
+                    format!("unexpected {:?}", keyword),
+                ))
+            }
+        }
+    }
+    if corners.is_some() {
+        return Err(LoadError::parse(
+            Position::Line(line),
+            "file ends inside a facet",
+        ));
+    }
+    if vertices.data.indices.is_empty() {
+        return Err(LoadError::parse(Position::Line(line), "file has no faces"));
+    }
+    Ok(vertices.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A binary STL file of the given triangles.
+    fn stl(triangles: &[[[f32; 3]; 3]]) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER];
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            bytes.extend_from_slice(&[0; 12]);
+            for x in triangle.iter().flatten() {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 2]);
+        }
+        bytes
+    }
+
+    fn square() -> Vec<u8> {
+        stl(&[
+            [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.]],
+            [[0., 0., 0.], [1., 1., 0.], [0., 1., 0.]],
+        ])
+    }
+
+    const ASCII_SQUARE: &str = "solid square
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 1 1 0
+    endloop
+  endfacet
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 1 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid square
+";
+
+    fn error_line(text: &str) -> usize {
+        match parse_stl(text.as_bytes()) {
+            Err(LoadError::Parse {
+                position: Position::Line(line),
+                ..
+            }) => line,
+            Err(e) => panic!("expected an error with a line, got {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn shared_corners_merge() {
+        let data = parse_stl(&square()).unwrap();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.indices, [[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(data.positions[3], Vec3(0., 1., 0.));
+    }
+
+    #[test]
+    fn ascii() {
+        let data = parse_stl(ASCII_SQUARE.as_bytes()).unwrap();
+        let binary = parse_stl(&square()).unwrap();
+        assert_eq!(data.positions, binary.positions);
+        assert_eq!(data.indices, binary.indices);
+
+        // A binary file whose header happens to start with "solid".
+        let mut bytes = square();
+        bytes[..5].copy_from_slice(b"solid");
+        assert_eq!(parse_stl(&bytes).unwrap().indices.len(), 2);
+    }
+
+    #[test]
+    fn ascii_malformed() {
+        assert_eq!(
+            error_line(&ASCII_SQUARE.replace("vertex 1 0 0", "vertex 1 0")),
+            5
+        );
+        assert_eq!(
+            error_line(&ASCII_SQUARE.replace("vertex 1 0 0", "vertex 1 x 0")),
+            5
+        );
+        assert_eq!(
+            error_line(&ASCII_SQUARE.replace("vertex 1 0 0", "vertex 1 nan 0")),
+            5
+        );
+        assert_eq!(
+            error_line(&ASCII_SQUARE.replacen("vertex 1 1 0\n", "", 1)),
+            6
+        );
+        assert_eq!(error_line(&ASCII_SQUARE.replacen("outer loop", "", 1)), 4);
+        assert_eq!(
+            error_line(&ASCII_SQUARE.replace("endfacet", "endfacets")),
+            8
+        );
+        assert_eq!(error_line("solid empty\nendsolid empty\n"), 2);
+    }
+
+    #[test]
+    fn malformed() {
+        let mut ascii = b"solid square\n".to_vec();
+        ascii.resize(200, b' ');
+        for bytes in &[ascii, stl(&[]), square()[..HEADER + 4].to_vec()] {
+            assert!(matches!(parse_stl(bytes), Err(LoadError::Parse { .. })));
+        }
+    }
+
+    #[test]
+    fn non_finite() {
+        let bytes = stl(&[
+            [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.]],
+            [[0., 0., 0.], [1., f32::NAN, 0.], [0., 1., 0.]],
+        ]);
+        assert_eq!(
+            parse_stl(&bytes).err().unwrap().to_string(),
+            "byte 158: vertex has a coordinate that isn't finite"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = square();
+        for len in 0..bytes.len() {
+            assert!(parse_stl(&bytes[..len]).is_err(), "read {} bytes", len);
+        }
+        for len in 0..ASCII_SQUARE.len() {
+            // Any outcome but a panic will do, as the file may end right
+            // after a facet.
+            let _ = parse_stl(&ASCII_SQUARE.as_bytes()[..len]);
+        }
+    }
+}
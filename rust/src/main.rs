@@ -1,43 +1,283 @@
-use rand::prelude::*;
+use clap::{Parser, ValueEnum};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use raytrace::{objects::Object, scenes::*, *};
+use raytrace::{
+    integrator::{Albedo, AmbientOcclusion, Depth, LightSampling, Mis, Normals, Uv},
+    loaders::load_gltf,
+    scene_file::load_scene,
+    scenes::{self, Scene, SCENES},
+    *,
+};
 
-const USE_BVH: bool = true;
+/// Renders one of the built-in scenes or a scene file to an image.
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// Built-in scene name (see --list-scenes), or a .ron, .gltf or .glb file.
+    #[arg(long, default_value = "book_final")]
+    scene: String,
+    /// Width of the image in pixels.
+    #[arg(long, default_value_t = 200, value_parser = at_least_one)]
+    width: usize,
+    /// Height of the image in pixels.
+    #[arg(long, default_value_t = 200, value_parser = at_least_one)]
+    height: usize,
+    /// Samples per pixel [default: recommended by the scene].
+    #[arg(long, value_parser = at_least_one)]
+    spp: Option<usize>,
+    /// Maximum number of bounces per path.
+    #[arg(long)]
+    depth: Option<usize>,
+    /// Terminate paths by Russian roulette after this many bounces.
+    #[arg(long)]
+    roulette: Option<usize>,
+    /// Seed for scene generation and rendering.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Number of render threads [default: one per core].
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Image to write; the format is chosen by the extension, and `-` prints
+    /// an ASCII PPM to standard output.
+    #[arg(long, default_value = "renders/final.png")]
+    output: PathBuf,
+    /// Tone map for 8-bit output: clamp, reinhard, extended-reinhard:WHITE,
+    /// aces or hable.
+    #[arg(long, default_value = "clamp")]
+    tonemap: ToneMap,
+    /// Exposure adjustment in stops for 8-bit output.
+    #[arg(long, default_value_t = 0., value_parser = finite, allow_negative_numbers = true)]
+    exposure: f64,
+    /// Test every ray against every object instead of building a BVH.
+    #[arg(long)]
+    no_bvh: bool,
+    /// How to compute the radiance along each camera ray.
+    #[arg(long, value_enum, default_value_t = IntegratorKind::Path)]
+    integrator: IntegratorKind,
+    /// List the built-in scenes and exit.
+    #[arg(long)]
+    list_scenes: bool,
+}
+
+/// Parses a count that must not be zero.
+fn at_least_one(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+/// Parses a number that must be finite.
+fn finite(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x.is_finite() => Ok(x),
+        Ok(_) => Err("must be a finite number".to_string()),
+        Err(e) => Err(format!("{}", e)),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum IntegratorKind {
+    /// Plain path tracing.
+    Path,
+    /// Path tracing with direct light sampling.
+    Light,
+    /// Path tracing with multiple importance sampling.
+    Mis,
+    /// Path tracing at single wavelengths, with emitters' full spectra.
+    Spectral,
+    /// Ambient occlusion.
+    Ao,
+    /// Surface normals of the first hit.
+    Normals,
+    /// Texture coordinates of the first hit.
+    Uv,
+    /// Distance to the first hit.
+    Depth,
+    /// Unlit material color of the first hit.
+    Albedo,
+}
 
 fn main() {
-    const ASPECT_RATIO: f64 = 1.0 / 1.0;
-    const NX: usize = 200;
-    const NY: usize = (NX as f64 / ASPECT_RATIO) as usize;
-    const NS: usize = 500;
+    let args = Args::parse();
+
+    if args.list_scenes {
+        for scene in SCENES {
+            println!(
+                "{:<16} {:>5} spp  {}",
+                scene.name, scene.samples, scene.description
+            );
+        }
+        return;
+    }
+
+    let defaults = RenderSettings::default();
+    let builtin = scenes::find(&args.scene);
+    let settings = RenderSettings {
+        width: args.width,
+        height: args.height,
+        samples: args
+            .spp
+            .unwrap_or_else(|| builtin.map_or(defaults.samples, |s| s.samples)),
+        max_depth: args.depth.unwrap_or(defaults.max_depth),
+        roulette_depth: args.roulette,
+        seed: args.seed.unwrap_or(defaults.seed),
+        threads: args.threads,
+        ..defaults
+    };
+    let (nx, ny) = (settings.width, settings.height);
+
+    let scene = match open_scene(&args.scene, &settings) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let faces: usize = scene.objects.iter().map(|object| object.faces()).sum();
+    if faces > 0 {
+        eprintln!("Loaded {} triangles.", faces);
+    }
 
     eprintln!(
         "Parallel casting {} x {} image using {}x oversampling.",
-        NX, NY, NS
+        nx, ny, settings.samples
     );
 
-    let mut rng = rand::rngs::SmallRng::seed_from_u64(0xDEADBEEF);
-    rng.gen::<f64>();
+    let Scene {
+        objects: world,
+        camera,
+        exposure,
+        environment,
+        lights,
+    } = scene;
 
-    // let (world, camera, exposure) = cornell_box_scene(NX, NY);
-    // let (world, camera, exposure) = simple_light_scene(NX, NY, &mut rng);
-    // let (world, camera, exposure) = volume_test(NX, NY);
-    let (world, camera, exposure) = book_final_scene(NX, NY, &mut rng);
-    // let (world, camera, exposure) = scene_textured_sphere(NX, NY);
+    if matches!(args.integrator, IntegratorKind::Light | IntegratorKind::Mis) && lights.is_empty() {
+        eprintln!("Warning: the scene has no lights to sample; this is plain path tracing.");
+    }
+    let extent = world
+        .iter()
+        .map(|object| object.bounding_box(exposure.clone()))
+        .reduce(|a, b| a.merge(b))
+        .map_or(1., |aabb| (aabb.max - aabb.min).length());
 
-    let (image, time) = if USE_BVH {
+    let start = Instant::now();
+    let image = if args.no_bvh {
+        eprintln!("Testing every ray against every object.");
+        let world = WithEnvironment {
+            world: &world[..],
+            environment,
+        };
+        render(&settings, &camera, world, args.integrator, lights, extent)
+    } else {
         eprintln!("Generating bounding volume hierarchy.");
-        let world = bvh::Bvh::new(world, exposure);
+        let world = WithEnvironment {
+            world: bvh::Bvh::new(world, exposure),
+            environment,
+        };
         eprintln!("Done.");
-        let start = Instant::now();
-        (par_cast(NX, NY, NS, &camera, world), start.elapsed())
-    } else {
-        eprintln!("Testing every ray against every object.");
-        let world: &[Box<dyn Object>] = &world;
-        let start = Instant::now();
-        (par_cast(NX, NY, NS, &camera, world), start.elapsed())
+        render(&settings, &camera, world, args.integrator, lights, extent)
     };
+    let time = start.elapsed();
 
     eprintln!("Took {:?} wall time.", time);
-    print_ppm(image);
+
+    let display = DisplayTransform {
+        exposure: args.exposure,
+        tone_map: args.tonemap,
+    };
+    if args.output == Path::new("-") {
+        print_ppm(image, &display);
+        return;
+    }
+    let saved = match args.output.parent() {
+        Some(dir) => std::fs::create_dir_all(dir).map_err(Into::into),
+        None => Ok(()),
+    }
+    .and_then(|()| image.save_with(&args.output, &display));
+    if let Err(e) = saved {
+        eprintln!("{}: {}", args.output.display(), e);
+        std::process::exit(1);
+    }
+    eprintln!("Saved {}.", args.output.display());
+}
+
+/// Builds the built-in scene called `name`, or loads it from a file if `name`
+/// has a scene file extension.
+// TODO: Remove boxed error
+fn open_scene(name: &str, settings: &RenderSettings) -> Result<Scene, Box<dyn std::error::Error>> {
+    let (nx, ny) = (settings.width, settings.height);
+    let path = Path::new(name);
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("ron") => {
+            load_scene(path, settings.aspect_ratio()).map_err(|e| format!("{}: {}", name, e).into())
+        }
+        Some("gltf") | Some("glb") => {
+            let scene =
+                load_gltf(path, settings.aspect_ratio()).map_err(|e| format!("{}: {}", name, e))?;
+            let camera = scene
+                .camera
+                .ok_or_else(|| format!("{}: no perspective camera in file", name))?;
+            Ok(Scene {
+                objects: scene.objects,
+                camera,
+                exposure: 0.0..1.0,
+                environment: Environment::default(),
+                lights: scene.lights,
+            })
+        }
+        _ => match scenes::find(name) {
+            Some(scene) => Ok(scene.build(nx, ny, settings.seed)),
+            None => Err(format!(
+                "unknown scene '{}'; see --list-scenes for the built-in ones",
+                name
+            )
+            .into()),
+        },
+    }
+}
+
+/// Renders `world` with the integrator of the given kind. `extent` is the
+/// size of the scene, which scales the distances of the debug views.
+fn render(
+    settings: &RenderSettings,
+    camera: &Camera,
+    world: impl World,
+    kind: IntegratorKind,
+    lights: Vec<Box<dyn PdfObject>>,
+    extent: f64,
+) -> Image {
+    match kind {
+        IntegratorKind::Path => par_cast(settings, camera, world, &PathTracer),
+        IntegratorKind::Light => par_cast(settings, camera, world, &LightSampling { lights }),
+        IntegratorKind::Mis => par_cast(
+            settings,
+            camera,
+            world,
+            &Mis {
+                lights,
+                heuristic: Heuristic::Power,
+            },
+        ),
+        IntegratorKind::Spectral => par_cast(settings, camera, world, &Spectral),
+        IntegratorKind::Ao => par_cast(
+            settings,
+            camera,
+            world,
+            &AmbientOcclusion {
+                distance: 0.1 * extent,
+            },
+        ),
+        IntegratorKind::Normals => par_cast(settings, camera, world, &Normals),
+        IntegratorKind::Uv => par_cast(settings, camera, world, &Uv),
+        IntegratorKind::Depth => par_cast(settings, camera, world, &Depth { far: extent }),
+        IntegratorKind::Albedo => par_cast(settings, camera, world, &Albedo),
+    }
 }
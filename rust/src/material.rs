@@ -1,17 +1,20 @@
 use rand::prelude::*;
 
 use crate::{
+    microfacet::{fresnel_conductor, fresnel_dielectric, Ggx},
     objects::HitRecord,
     onb::{AxisBasis::*, Onb},
     pdf,
     ray::Ray,
+    scene_file::MaterialDesc,
+    spectrum::{self, Dispersion, Spd},
     texture::Texture,
     vec3::{reflect, refract, Vec3},
     PI,
 };
 
 /// Material options for a rendered object.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum Material {
     /// An opaque material with a matte surface, where lighting is calculated
     /// using [Lambertian reflectance][lambert].
@@ -29,6 +32,16 @@ pub enum Material {
         /// of 1 produces a frosted, almost matte surface.
         fuzz: f64,
     },
+    /// A rough metal, modelled as a surface of mirror microfacets with the GGX
+    /// distribution of normals and Smith masking-shadowing.
+    Conductor {
+        /// Complex refractive index of the metal, which sets its color and how
+        /// that color shifts towards grazing angles.
+        ior: ComplexIor,
+        /// GGX roughness: 0 is a perfect mirror, around 0.1 looks brushed and
+        /// 0.5 and over nearly matte.
+        roughness: f64,
+    },
     /// A transparent refractive material like glass or water.
     Dielectric {
         /// [Refractive index][ref-idx] of the material, which determines how
@@ -36,13 +49,70 @@ pub enum Material {
         ///
         /// [ref-idx]: https://en.wikipedia.org/wiki/Refractive_index
         ref_idx: f64,
+        /// Fraction of light absorbed per unit distance travelled inside the
+        /// object, in each color component, so that crossing a thickness `d`
+        /// leaves `exp(-absorption * d)` of it. Zero gives clear glass.
+        ///
+        /// Absorption is applied when a ray leaves the object or reflects
+        /// inside it, so it needs closed objects that don't overlap others.
+        absorption: Vec3,
+    },
+    /// A dielectric with a rough surface, like frosted glass or etched
+    /// acrylic. The surface is made of GGX microfacets which blur both the
+    /// reflection and the refraction, after Walter et al., "Microfacet Models
+    /// for Refraction through Rough Surfaces" (2007).
+    RoughDielectric {
+        /// Refractive index of the material, as for `Dielectric`.
+        ref_idx: f64,
+        /// GGX roughness, as for `Conductor`.
+        roughness: f64,
+        /// Absorption inside the object, as for `Dielectric`.
+        absorption: Vec3,
+    },
+    /// A dielectric whose refractive index varies with the wavelength, which
+    /// splits white light into its colors; otherwise like `RoughDielectric`.
+    ///
+    /// Rays scatter at the wavelength they carry, which the integrators
+    /// commit paths to before scattering off it; see `spectrum`.
+    Dispersive {
+        dispersion: Dispersion,
+        /// GGX roughness, as for `Conductor`.
+        roughness: f64,
+        /// Absorption inside the object, as for `Dielectric`.
+        absorption: Vec3,
     },
     /// Diffuse light.
     DiffuseLight { emission: Texture, brightness: f64 },
+    /// Diffuse light with a given spectrum, such as that of a black body.
+    SpectralLight { spd: Spd, brightness: f64 },
     /// Isotropic scattering.
     Isotropic { albedo: Texture },
 }
 
+/// Complex refractive index `eta + ik` of a conductor, per color channel,
+/// measured at wavelengths of about 650, 550 and 450 nm.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ComplexIor {
+    pub eta: Vec3,
+    /// Extinction coefficient.
+    pub k: Vec3,
+}
+
+impl ComplexIor {
+    pub const GOLD: ComplexIor = ComplexIor {
+        eta: Vec3(0.143, 0.374, 1.442),
+        k: Vec3(3.983, 2.385, 1.603),
+    };
+    pub const COPPER: ComplexIor = ComplexIor {
+        eta: Vec3(0.200, 0.924, 1.102),
+        k: Vec3(3.912, 2.452, 2.142),
+    };
+    pub const ALUMINIUM: ComplexIor = ComplexIor {
+        eta: Vec3(1.657, 0.880, 0.521),
+        k: Vec3(9.224, 6.270, 4.837),
+    };
+}
+
 impl Material {
     /// Performs surface scattering from a material.
     ///
@@ -71,10 +141,10 @@ impl Material {
                 let scattered = Ray {
                     origin: hit.p,
                     direction: direction.into_unit(),
-                    time: ray.time,
+                    ..*ray
                 };
                 let pdf = uvw[W].dot(scattered.direction) / PI;
-                Some((scattered, albedo(hit.u, hit.v, hit.p), pdf))
+                Some((scattered, albedo.value(hit.u, hit.v, hit.p), pdf))
             }
             Material::Metal { albedo, fuzz } => {
                 let scattered = Ray {
@@ -91,7 +161,50 @@ impl Material {
                     None
                 }
             }
-            Material::Dielectric { ref_idx } => {
+            Material::Conductor { ior, roughness } => {
+                let (uvw, wo) = facing_frame(ray, hit);
+                if wo.2 <= 0. {
+                    return None;
+                }
+                if *roughness < SMOOTH {
+                    let direction = uvw.local(Vec3(-wo.0, -wo.1, wo.2));
+                    let reflectance = fresnel_conductor(wo.2, ior.eta, ior.k);
+                    return Some((
+                        Ray {
+                            origin: hit.p,
+                            direction,
+                            ..*ray
+                        },
+                        reflectance,
+                        0.,
+                    ));
+                }
+
+                let ggx = Ggx { alpha: *roughness };
+                let h = ggx.sample_visible(wo, rng.gen(), rng.gen());
+                let wi = reflect(-wo, h);
+                if wi.2 <= 0. {
+                    return None;
+                }
+                // The distribution term cancels against the density of the
+                // visible normal, leaving Fresnel and the masking ratio.
+                let weight =
+                    fresnel_conductor(wo.dot(h), ior.eta, ior.k) * ggx.g2(wo, wi) / ggx.g1(wo);
+                let pdf = ggx.visible_d(wo, h) / (4. * wo.dot(h));
+                Some((
+                    Ray {
+                        origin: hit.p,
+                        direction: uvw.local(wi),
+                        ..*ray
+                    },
+                    weight,
+                    pdf,
+                ))
+            }
+            Material::Dielectric {
+                ref_idx,
+                absorption,
+            } => {
                 let (outward_normal, ni_over_nt, cosine) = if ray.direction.dot(hit.normal) > 0. {
                     (
                         -hit.normal,
@@ -110,28 +223,80 @@ impl Material {
                     .filter(|_| rng.gen::<f64>() >= schlick(cosine, *ref_idx))
                     .unwrap_or_else(|| reflect(ray.direction, hit.normal));
 
-                let attenuation = Vec3::from(1.);
+                let attenuation = transmittance(*absorption, ray, hit);
                 let ray = Ray {
                     origin: hit.p,
                     direction,
-                    time: ray.time,
+                    ..*ray
                 };
                 Some((ray, attenuation, 0.))
             }
-            Material::DiffuseLight { .. } => None,
+            Material::RoughDielectric {
+                ref_idx,
+                roughness,
+                absorption,
+            } => {
+                let (uvw, wo, eta) = dielectric_frame(ray, hit, *ref_idx);
+                if wo.2 <= 0. {
+                    return None;
+                }
+                let attenuation = transmittance(*absorption, ray, hit);
+                if *roughness < SMOOTH {
+                    let reflected = Vec3(-wo.0, -wo.1, wo.2);
+                    let wi = refract(-wo, Vec3(0., 0., 1.), 1. / eta)
+                        .filter(|_| rng.gen::<f64>() >= fresnel_dielectric(wo.2, eta))
+                        .unwrap_or(reflected);
+                    return Some((
+                        Ray {
+                            origin: hit.p,
+                            direction: uvw.local(wi),
+                            ..*ray
+                        },
+                        attenuation,
+                        0.,
+                    ));
+                }
+
+                // Reflect or refract at a visible microfacet with the
+                // probability given by its Fresnel term, which then cancels
+                // from the attenuation along with the distribution term.
+                let ggx = Ggx { alpha: *roughness };
+                let h = ggx.sample_visible(wo, rng.gen(), rng.gen());
+                let reflectance = fresnel_dielectric(wo.dot(h), eta);
+                let wi = if rng.gen::<f64>() < reflectance {
+                    Some(reflect(-wo, h)).filter(|wi| wi.2 > 0.)
+                } else {
+                    refract(-wo, h, 1. / eta).filter(|wi| wi.2 < 0.)
+                }?;
+                let scattered = Ray {
+                    origin: hit.p,
+                    direction: uvw.local(wi.into_unit()),
+                    ..*ray
+                };
+                let weight = attenuation * ggx.g2(wo, wi) / ggx.g1(wo);
+                Some((scattered, weight, self.scattering_pdf(ray, hit, &scattered)))
+            }
+            Material::Dispersive {
+                dispersion,
+                roughness,
+                absorption,
+            } => dispersed(dispersion, *roughness, *absorption, ray).scatter(ray, hit, rng),
+            Material::DiffuseLight { .. } | Material::SpectralLight { .. } => None,
             Material::Isotropic { albedo } => Some((
                 Ray {
                     origin: hit.p,
                     direction: Vec3::in_unit_sphere(rng),
                     ..*ray
                 },
-                albedo(hit.u, hit.v, hit.p),
+                albedo.value(hit.u, hit.v, hit.p),
                 0.,
             )),
         }
     }
 
-    pub fn scattering_pdf(&self, _ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64 {
+    /// Density with which `scatter` draws the direction of `scattered`, or 0
+    /// for materials that don't report one.
+    pub fn scattering_pdf(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> f64 {
         match self {
             Material::Lambertian { .. } => {
                 let cosine = hit.normal.dot(scattered.direction.into_unit());
@@ -141,10 +306,196 @@ impl Material {
                     cosine / PI
                 }
             }
+            Material::Conductor { roughness, .. } if *roughness >= SMOOTH => {
+                let (uvw, wo) = facing_frame(ray, hit);
+                let wi = uvw.coords(scattered.direction.into_unit());
+                if wi.2 <= 0. {
+                    return 0.;
+                }
+                let h = (wo + wi).into_unit();
+                let ggx = Ggx { alpha: *roughness };
+                ggx.visible_d(wo, h) / (4. * wo.dot(h))
+            }
+            Material::RoughDielectric {
+                ref_idx, roughness, ..
+            } if *roughness >= SMOOTH => {
+                let (uvw, wo, eta) = dielectric_frame(ray, hit, *ref_idx);
+                let wi = uvw.coords(scattered.direction.into_unit());
+                let ggx = Ggx { alpha: *roughness };
+                match dielectric_half_vector(wo, wi, eta) {
+                    Some(h) if wi.2 > 0. => {
+                        fresnel_dielectric(wo.dot(h), eta) * ggx.visible_d(wo, h) / (4. * wo.dot(h))
+                    }
+                    Some(h) => {
+                        let denom = (wi.dot(h) + wo.dot(h) / eta).powi(2);
+                        (1. - fresnel_dielectric(wo.dot(h), eta))
+                            * ggx.visible_d(wo, h)
+                            * wi.dot(h).abs()
+                            / denom
+                    }
+                    None => 0.,
+                }
+            }
+            Material::Dispersive {
+                dispersion,
+                roughness,
+                absorption,
+            } => dispersed(dispersion, *roughness, *absorption, ray)
+                .scattering_pdf(ray, hit, scattered),
             _ => 0.,
         }
     }
 
+    /// The fraction of light arriving along `scattered` that leaves back along
+    /// `ray`, that is the BSDF times the cosine of `scattered` to the normal.
+    ///
+    /// Dividing this by `scattering_pdf` gives the attenuation `scatter`
+    /// reports for the same direction. Materials that don't report a density
+    /// return black.
+    pub fn eval(&self, ray: &Ray, hit: &HitRecord, scattered: &Ray) -> Vec3 {
+        match self {
+            Material::Lambertian { albedo } => {
+                albedo.value(hit.u, hit.v, hit.p) * self.scattering_pdf(ray, hit, scattered)
+            }
+            Material::Conductor { ior, roughness } if *roughness >= SMOOTH => {
+                let (uvw, wo) = facing_frame(ray, hit);
+                let wi = uvw.coords(scattered.direction.into_unit());
+                if wo.2 <= 0. || wi.2 <= 0. {
+                    return Vec3::default();
+                }
+                let h = (wo + wi).into_unit();
+                let ggx = Ggx { alpha: *roughness };
+                fresnel_conductor(wo.dot(h), ior.eta, ior.k) * ggx.d(h) * ggx.g2(wo, wi)
+                    / (4. * wo.2)
+            }
+            Material::RoughDielectric {
+                ref_idx,
+                roughness,
+                absorption,
+            } if *roughness >= SMOOTH => {
+                let (uvw, wo, eta) = dielectric_frame(ray, hit, *ref_idx);
+                let wi = uvw.coords(scattered.direction.into_unit());
+                let ggx = Ggx { alpha: *roughness };
+                let h = match dielectric_half_vector(wo, wi, eta) {
+                    Some(h) => h,
+                    None => return Vec3::default(),
+                };
+                let reflectance = fresnel_dielectric(wo.dot(h), eta);
+                // Like `Dielectric`, transmitted radiance isn't rescaled by
+                // the squared ratio of refractive indices, which cancels out
+                // on leaving a closed object anyway. Absorption belongs to
+                // the path back along `ray`, and is included here as it is in
+                // `scatter`.
+                transmittance(*absorption, ray, hit)
+                    * if wi.2 > 0. {
+                        reflectance * ggx.d(h) * ggx.g2(wo, wi) / (4. * wo.2)
+                    } else {
+                        let denom = (wi.dot(h) + wo.dot(h) / eta).powi(2);
+                        (1. - reflectance)
+                            * ggx.d(h)
+                            * ggx.g2(wo, wi)
+                            * (wi.dot(h) * wo.dot(h)).abs()
+                            / (denom * wo.2)
+                    }
+            }
+            Material::Dispersive {
+                dispersion,
+                roughness,
+                absorption,
+            } => dispersed(dispersion, *roughness, *absorption, ray).eval(ray, hit, scattered),
+            _ => Vec3::default(),
+        }
+    }
+
+    /// The color of the material at the hit position, regardless of lighting.
+    pub fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        match self {
+            Material::Lambertian { albedo } | Material::Isotropic { albedo } => {
+                albedo.value(hit.u, hit.v, hit.p)
+            }
+            Material::Metal { albedo, .. } => *albedo,
+            Material::Conductor { ior, .. } => fresnel_conductor(1., ior.eta, ior.k),
+            Material::Dielectric { .. }
+            | Material::RoughDielectric { .. }
+            | Material::Dispersive { .. } => Vec3::from(1.),
+            Material::DiffuseLight { emission, .. } => emission.value(hit.u, hit.v, hit.p),
+            Material::SpectralLight { spd, .. } => spd.rgb(),
+        }
+    }
+
+    /// Describes the material for a scene file, or returns `None` if one of
+    /// its textures can't be described.
+    pub fn describe(&self) -> Option<MaterialDesc> {
+        Some(match self {
+            Material::Lambertian { albedo } => MaterialDesc::Lambertian {
+                albedo: albedo.describe()?,
+            },
+            Material::Metal { albedo, fuzz } => MaterialDesc::Metal {
+                albedo: *albedo,
+                fuzz: *fuzz,
+            },
+            Material::Conductor { ior, roughness } => MaterialDesc::Conductor {
+                eta: ior.eta,
+                k: ior.k,
+                roughness: *roughness,
+            },
+            Material::Dielectric {
+                ref_idx,
+                absorption,
+            } => MaterialDesc::Dielectric {
+                ref_idx: *ref_idx,
+                absorption: *absorption,
+            },
+            Material::RoughDielectric {
+                ref_idx,
+                roughness,
+                absorption,
+            } => MaterialDesc::RoughDielectric {
+                ref_idx: *ref_idx,
+                roughness: *roughness,
+                absorption: *absorption,
+            },
+            Material::Dispersive {
+                dispersion,
+                roughness,
+                absorption,
+            } => MaterialDesc::Dispersive {
+                dispersion: *dispersion,
+                roughness: *roughness,
+                absorption: *absorption,
+            },
+            Material::DiffuseLight {
+                emission,
+                brightness,
+            } => MaterialDesc::DiffuseLight {
+                emission: emission.describe()?,
+                brightness: *brightness,
+            },
+            Material::SpectralLight { spd, brightness } => MaterialDesc::SpectralLight {
+                spd: spd.describe(),
+                brightness: *brightness,
+            },
+            Material::Isotropic { albedo } => MaterialDesc::Isotropic {
+                albedo: albedo.describe()?,
+            },
+        })
+    }
+
+    /// Whether the material scatters light differently depending on its
+    /// wavelength, so that paths must commit to one before scattering off it.
+    pub fn is_dispersive(&self) -> bool {
+        matches!(self, Material::Dispersive { .. })
+    }
+
+    /// Whether the material gives off light, which makes objects made of it
+    /// worth sampling directly.
+    pub fn is_emitter(&self) -> bool {
+        matches!(
+            self,
+            Material::DiffuseLight { .. } | Material::SpectralLight { .. }
+        )
+    }
+
     /// Perfoms a light emitting from a light sources. The all non-emitting
     /// materials return black colour by default.
     // TODO: Remove reference to `HitRecord` which is self.
@@ -155,22 +506,99 @@ impl Material {
                 brightness,
             } => {
                 if p.into_unit().dot(hit.normal) > 0. {
-                    *brightness * emission(u, v, p)
+                    *brightness * emission.value(u, v, p)
                 } else {
                     Vec3::from(0.)
                 }
             }
+            Material::SpectralLight { spd, brightness } if p.into_unit().dot(hit.normal) > 0. => {
+                *brightness * spd.rgb()
+            }
             _ => Vec3::default(),
         }
     }
+
+    /// Like `emitted`, but the power at the single wavelength `lambda`, for
+    /// spectral rendering. RGB emission is upsampled to a spectrum.
+    pub fn emitted_spectral(&self, lambda: f64, u: f64, v: f64, p: Vec3, hit: &HitRecord) -> f64 {
+        match self {
+            Material::SpectralLight { spd, brightness } if p.into_unit().dot(hit.normal) > 0. => {
+                *brightness * spd.value(lambda)
+            }
+            Material::SpectralLight { .. } => 0.,
+            _ => spectrum::rgb_to_spectrum(self.emitted(u, v, p, hit), lambda),
+        }
+    }
+}
+
+/// Roughness below which microfacet materials are treated as perfectly
+/// smooth, as their distributions become too sharp to evaluate.
+const SMOOTH: f64 = 1e-3;
+
+/// The `RoughDielectric` that a `Dispersive` material acts as at the
+/// wavelength `ray` carries, or at the helium d line of 587.6 nm if it
+/// carries none.
+fn dispersed(dispersion: &Dispersion, roughness: f64, absorption: Vec3, ray: &Ray) -> Material {
+    Material::RoughDielectric {
+        ref_idx: dispersion.ior(ray.wavelength.unwrap_or(587.6)),
+        roughness,
+        absorption,
+    }
+}
+
+/// Basis around the normal on the side of the surface facing `ray`, and the
+/// direction back along `ray` in that basis.
+fn facing_frame(ray: &Ray, hit: &HitRecord) -> (Onb, Vec3) {
+    let normal = if ray.direction.dot(hit.normal) > 0. {
+        -hit.normal
+    } else {
+        hit.normal
+    };
+    let uvw = Onb::build_from_w(normal);
+    let wo = uvw.coords(-ray.direction.into_unit());
+    (uvw, wo)
 }
 
-impl std::fmt::Debug for Material {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        f.write_str("...")
+/// Fraction of light surviving absorption along `ray` up to `hit`, if the ray
+/// is leaving or reflecting inside the object, having travelled through it.
+fn transmittance(absorption: Vec3, ray: &Ray, hit: &HitRecord) -> Vec3 {
+    if ray.direction.dot(hit.normal) > 0. {
+        let distance = hit.t * ray.direction.length();
+        absorption.map(|a| (-a * distance).exp())
+    } else {
+        Vec3::from(1.)
     }
 }
 
+/// Like `facing_frame`, also returning the ratio of the refractive index
+/// beyond the surface to the one on the side facing `ray`.
+fn dielectric_frame(ray: &Ray, hit: &HitRecord, ref_idx: f64) -> (Onb, Vec3, f64) {
+    let (uvw, wo) = facing_frame(ray, hit);
+    let eta = if ray.direction.dot(hit.normal) > 0. {
+        1. / ref_idx
+    } else {
+        ref_idx
+    };
+    (uvw, wo, eta)
+}
+
+/// The microfacet normal that reflects or refracts `wo` into `wi`, given
+/// in the frame of `dielectric_frame`, or `None` if no microfacet facing both
+/// of them does.
+fn dielectric_half_vector(wo: Vec3, wi: Vec3, eta: f64) -> Option<Vec3> {
+    if wo.2 <= 0. || wi.2 == 0. {
+        return None;
+    }
+    let h = if wi.2 > 0. { wo + wi } else { wo + eta * wi };
+    if h.dot(h) == 0. {
+        return None;
+    }
+    let h = if h.2 < 0. { -h } else { h }.into_unit();
+    // Refraction needs the directions on opposite sides of the microfacet.
+    let refracted = wi.2 < 0.;
+    Some(h).filter(|h| wo.dot(*h) > 0. && (wi.dot(*h) < 0.) == refracted)
+}
+
 /// [Schlick's approximation][schlick] for computing reflection vs. refraction
 /// at a material surface.
 ///
@@ -181,3 +609,272 @@ fn schlick(cos: f64, ref_idx: f64) -> f64 {
     let r0 = r0 * r0;
     r0 + (1. - r0) * (1. - cos).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+
+    /// A hit at the origin on a surface facing `+Z`.
+    fn hit(material: &Material) -> HitRecord<'_> {
+        HitRecord {
+            t: 1.,
+            p: Vec3::default(),
+            u: 0.,
+            v: 0.,
+            normal: Vec3(0., 0., 1.),
+            material,
+        }
+    }
+
+    /// The ray reaching the origin from direction `w`.
+    fn arriving(w: Vec3) -> Ray {
+        Ray::new(w, -w, 0.)
+    }
+
+    /// The ray leaving the origin in direction `w`.
+    fn leaving(w: Vec3) -> Ray {
+        Ray::new(Vec3::default(), w, 0.)
+    }
+
+    /// Direction at cosine `z` to `+Z` and azimuth `phi`.
+    fn direction(z: f64, phi: f64) -> Vec3 {
+        let r = (1. - z * z).max(0.).sqrt();
+        Vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Number of cells along each side of the grid `cells` lays on the
+    /// sphere.
+    const N: usize = 8;
+
+    /// Integrals of `f` over the cells of a grid on the sphere, uniform in
+    /// polar angle and azimuth, from `m` x `m` jittered points per cell.
+    /// Jittering keeps the estimate unbiased where a density has a spike,
+    /// like refraction does at the edge of the directions it can reach.
+    fn cells(m: usize, f: impl Fn(Vec3) -> f64) -> Vec<f64> {
+        let mut rng = SmallRng::seed_from_u64(8);
+        let (dtheta, dphi) = (PI / (N * m) as f64, 2. * PI / (N * m) as f64);
+        let mut cells = vec![0.; N * N];
+        for i in 0..N * m {
+            for j in 0..N * m {
+                let theta = (i as f64 + rng.gen::<f64>()) * dtheta;
+                let phi = (j as f64 + rng.gen::<f64>()) * dphi;
+                let w = direction(theta.cos(), phi);
+                cells[i / m * N + j / m] += f(w) * theta.sin() * dtheta * dphi;
+            }
+        }
+        cells
+    }
+
+    /// The cell of the grid of `cells` that `w` falls into.
+    fn cell(w: Vec3) -> usize {
+        let theta = w.2.clamp(-1., 1.).acos();
+        let i = ((theta / PI * N as f64) as usize).min(N - 1);
+        let phi = w.1.atan2(w.0).rem_euclid(2. * PI);
+        let j = ((phi / (2. * PI) * N as f64) as usize).min(N - 1);
+        i * N + j
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.)
+    }
+
+    /// Checks that `scatter` draws directions with the density given by
+    /// `scattering_pdf`, reports that density, and weighs them by `eval`
+    /// over it, for light leaving towards `wo`.
+    fn check_sampling(material: &Material, wo: Vec3) {
+        let (hit, ray) = (hit(material), arriving(wo));
+        let mut rng = SmallRng::seed_from_u64(5);
+        let samples = 100_000;
+        let mut histogram = vec![0.; N * N];
+        for _ in 0..samples {
+            let (scattered, weight, pdf) = match material.scatter(&ray, &hit, &mut rng) {
+                Some(scattered) => scattered,
+                None => continue,
+            };
+            assert!(pdf > 0., "{:?}", scattered);
+            assert!(close(pdf, material.scattering_pdf(&ray, &hit, &scattered)));
+            let eval = material.eval(&ray, &hit, &scattered) / pdf;
+            assert!(
+                close(weight.0, eval.0) && close(weight.1, eval.1) && close(weight.2, eval.2),
+                "{:?} != {:?}",
+                weight,
+                eval
+            );
+            histogram[cell(scattered.direction.into_unit())] += 1. / samples as f64;
+        }
+
+        let expected = cells(24, |wi| material.scattering_pdf(&ray, &hit, &leaving(wi)));
+        for (found, expected) in histogram.iter().zip(&expected) {
+            assert!((found - expected).abs() < 5e-3, "{} != {}", found, expected);
+        }
+    }
+
+    /// Fraction of the light arriving from all directions that `material`
+    /// sends towards `wo`, by sampling `scatter`.
+    fn albedo(material: &Material, wo: Vec3) -> Vec3 {
+        let (hit, ray) = (hit(material), arriving(wo));
+        let mut rng = SmallRng::seed_from_u64(6);
+        let samples = 100_000;
+        (0..samples)
+            .filter_map(|_| material.scatter(&ray, &hit, &mut rng))
+            .fold(Vec3::default(), |sum, (_, weight, _)| sum + weight)
+            / samples as f64
+    }
+
+    /// The BSDF for light arriving from `wi` and leaving towards `wo`.
+    fn bsdf(material: &Material, wo: Vec3, wi: Vec3) -> Vec3 {
+        material.eval(&arriving(wo), &hit(material), &leaving(wi)) / wi.2.abs()
+    }
+
+    const WHITE_METAL: ComplexIor = ComplexIor {
+        eta: Vec3(0., 0., 0.),
+        k: Vec3(1., 1., 1.),
+    };
+
+    #[test]
+    fn conductor_sampling() {
+        for &roughness in &[0.2, 0.5, 1.] {
+            for &z in &[0.2, 0.7, 1.] {
+                let gold = Material::Conductor {
+                    ior: ComplexIor::GOLD,
+                    roughness,
+                };
+                check_sampling(&gold, direction(z, 0.4));
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_white_furnace() {
+        // A metal reflecting all light only loses what its microfacets
+        // shadow or send below the surface, which is next to nothing when
+        // smooth and grows with roughness.
+        for &z in &[0.2, 0.7, 1.] {
+            let mut last = 1.;
+            for &roughness in &[0.01, 0.05, 0.2, 0.5, 1.] {
+                let white = Material::Conductor {
+                    ior: WHITE_METAL,
+                    roughness,
+                };
+                let albedo = albedo(&white, direction(z, 0.4));
+                assert!(albedo.0 == albedo.1 && albedo.1 == albedo.2);
+                assert!(albedo.0 <= last, "{} {}: {}", roughness, z, albedo.0);
+                if roughness < 0.1 {
+                    assert!(albedo.0 > 0.95, "{} {}: {}", roughness, z, albedo.0);
+                }
+                last = albedo.0;
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_reciprocity() {
+        let mut rng = SmallRng::seed_from_u64(9);
+        let gold = Material::Conductor {
+            ior: ComplexIor::GOLD,
+            roughness: 0.3,
+        };
+        for _ in 0..1000 {
+            let wo = direction(rng.gen(), rng.gen_range(0. ..2. * PI));
+            let wi = direction(rng.gen(), rng.gen_range(0. ..2. * PI));
+            let (f, g) = (bsdf(&gold, wo, wi), bsdf(&gold, wi, wo));
+            assert!((f - g).length() <= 1e-9 * f.length(), "{:?} != {:?}", f, g);
+        }
+    }
+
+    fn glass(roughness: f64) -> Material {
+        Material::RoughDielectric {
+            ref_idx: 1.5,
+            roughness,
+            absorption: Vec3::default(),
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_sampling() {
+        for &roughness in &[0.4, 0.7, 1.] {
+            // From outside and from inside, where some light is reflected
+            // back in whole.
+            for &z in &[-1., -0.5, -0.2, 0.2, 0.7, 1.] {
+                check_sampling(&glass(roughness), direction(z, 0.4));
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_white_furnace() {
+        // Clear glass passes or reflects all light but what its microfacets
+        // shadow.
+        for &z in &[-1., -0.5, -0.2, 0.2, 0.7, 1.] {
+            let mut last = 1.;
+            for &roughness in &[0.01, 0.05, 0.2, 0.5, 1.] {
+                let albedo = albedo(&glass(roughness), direction(z, 0.4));
+                assert!(albedo.0 == albedo.1 && albedo.1 == albedo.2);
+                assert!(albedo.0 <= last, "{} {}: {}", roughness, z, albedo.0);
+                if roughness < 0.1 {
+                    assert!(albedo.0 > 0.95, "{} {}: {}", roughness, z, albedo.0);
+                }
+                last = albedo.0;
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_reciprocity() {
+        let mut rng = SmallRng::seed_from_u64(9);
+        let glass = glass(0.3);
+        let index = |w: Vec3| if w.2 > 0. { 1. } else { 1.5f64 };
+        let mut transmitted = 0;
+        for _ in 0..1000 {
+            let wo = direction(rng.gen_range(-1. ..1.), rng.gen_range(0. ..2. * PI));
+            let wi = direction(rng.gen_range(-1. ..1.), rng.gen_range(0. ..2. * PI));
+            // As `eval` doesn't rescale radiance on crossing the surface, the
+            // BSDF swaps the squared refractive indices of the usual rule.
+            let f = bsdf(&glass, wo, wi) * index(wo).powi(2);
+            let g = bsdf(&glass, wi, wo) * index(wi).powi(2);
+            assert!((f - g).length() <= 1e-9 * f.length(), "{:?} != {:?}", f, g);
+            if wo.2 * wi.2 < 0. && f.0 > 0. {
+                transmitted += 1;
+            }
+        }
+        assert!(transmitted > 10, "{}", transmitted);
+    }
+
+    #[test]
+    fn dispersive_scatters_at_ray_wavelength() {
+        let prism = Material::Dispersive {
+            dispersion: Dispersion::SF11,
+            roughness: 0.3,
+            absorption: Vec3::default(),
+        };
+        let mut rng = SmallRng::seed_from_u64(4);
+        for &lambda in &[450., 650.] {
+            let glass = Material::RoughDielectric {
+                ref_idx: Dispersion::SF11.ior(lambda),
+                roughness: 0.3,
+                absorption: Vec3::default(),
+            };
+            let ray = Ray {
+                wavelength: Some(lambda),
+                ..arriving(direction(0.6, 0.2))
+            };
+            let hit = hit(&prism);
+            for _ in 0..100 {
+                let (scattered, weight, pdf) = match prism.scatter(&ray, &hit, &mut rng) {
+                    Some(scattered) => scattered,
+                    None => continue,
+                };
+                assert_eq!(scattered.wavelength, Some(lambda));
+                assert_eq!(pdf, glass.scattering_pdf(&ray, &hit, &scattered));
+                let eval = glass.eval(&ray, &hit, &scattered) / pdf;
+                assert!(
+                    (weight - eval).length() < 1e-9,
+                    "{:?} != {:?}",
+                    weight,
+                    eval
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Microfacet models of rough surfaces, in a local frame where the
+//! macroscopic surface normal is `+Z`.
+
+use crate::{vec3::Vec3, PI};
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, with
+/// height-correlated Smith masking-shadowing.
+#[derive(Copy, Clone, Debug)]
+pub struct Ggx {
+    /// Roughness, the slope spread of the microfacets.
+    pub alpha: f64,
+}
+
+impl Ggx {
+    /// Density of microfacets with normal `h`, per unit projected area.
+    pub fn d(&self, h: Vec3) -> f64 {
+        if h.2 <= 0. {
+            return 0.;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.2 * h.2 * (a2 - 1.) + 1.;
+        a2 / (PI * t * t)
+    }
+
+    /// Smith's auxiliary function, the ratio of hidden to visible projected
+    /// microfacet area seen from `w`.
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.2 * w.2;
+        if cos2 == 0. {
+            return f64::INFINITY;
+        }
+        let tan2 = (1. - cos2).max(0.) / cos2;
+        0.5 * (-1. + (1. + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1. / (1. + self.lambda(w))
+    }
+
+    /// Fraction of microfacets visible from both `wo` and `wi`.
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1. / (1. + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the microfacet normal `h` among those visible from `wo`.
+    pub fn visible_d(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.2 <= 0. {
+            return 0.;
+        }
+        self.g1(wo) * wo.dot(h).max(0.) * self.d(h) / wo.2
+    }
+
+    /// Draws a microfacet normal visible from `wo`, distributed by
+    /// `visible_d`, after Heitz, "Sampling the GGX Distribution of Visible
+    /// Normals" (2018). `wo` must be above the surface.
+    pub fn sample_visible(&self, wo: Vec3, u1: f64, u2: f64) -> Vec3 {
+        // Stretch to the configuration where the distribution is a
+        // hemisphere of unit roughness.
+        let vh = Vec3(self.alpha * wo.0, self.alpha * wo.1, wo.2).into_unit();
+
+        let len2 = vh.0 * vh.0 + vh.1 * vh.1;
+        let t1 = if len2 > 0. {
+            Vec3(-vh.1, vh.0, 0.) / len2.sqrt()
+        } else {
+            Vec3(1., 0., 0.)
+        };
+        let t2 = vh.cross(&t1);
+
+        // Uniform point on the projected disc, squashed onto the visible half.
+        let r = u1.sqrt();
+        let phi = 2. * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1. + vh.2);
+        let p2 = (1. - s) * (1. - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1. - p1 * p1 - p2 * p2).max(0.).sqrt() * vh;
+
+        // Unstretch.
+        Vec3(self.alpha * nh.0, self.alpha * nh.1, nh.2.max(0.)).into_unit()
+    }
+}
+
+/// Fraction of light reflected by a conductor with complex refractive index
+/// `eta + ik` relative to the outside medium, per channel, for light arriving
+/// at an angle with cosine `cos` to the normal.
+pub fn fresnel_conductor(cos: f64, eta: Vec3, k: Vec3) -> Vec3 {
+    let cos = cos.clamp(0., 1.);
+    let cos2 = cos * cos;
+    let sin2 = 1. - cos2;
+    eta.zip_with(k, |eta, k| {
+        let (eta2, k2) = (eta * eta, k * k);
+        let t0 = eta2 - k2 - sin2;
+        let a2b2 = (t0 * t0 + 4. * eta2 * k2).sqrt();
+        let t1 = a2b2 + cos2;
+        let a = (0.5 * (a2b2 + t0)).max(0.).sqrt();
+        let t2 = 2. * cos * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rs + rp)
+    })
+}
+
+/// Fraction of unpolarized light reflected at the boundary of a dielectric,
+/// for light arriving at an angle with cosine `cos` to the normal on the side
+/// where the ratio of the refractive index beyond the boundary to the one
+/// before it is `eta`. Returns 1 for total internal reflection.
+pub fn fresnel_dielectric(cos: f64, eta: f64) -> f64 {
+    let cos = cos.clamp(0., 1.);
+    let sin2_t = (1. - cos * cos) / (eta * eta);
+    if sin2_t >= 1. {
+        return 1.;
+    }
+    let cos_t = (1. - sin2_t).sqrt();
+    let r_parallel = (eta * cos - cos_t) / (eta * cos + cos_t);
+    let r_perpendicular = (cos - eta * cos_t) / (cos + eta * cos_t);
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+    /// Direction at cosine `z` to the normal and azimuth `phi`.
+    fn direction(z: f64, phi: f64) -> Vec3 {
+        let r = (1. - z * z).max(0.).sqrt();
+        Vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Integrals of `f` over the cells of an `n` x `n` grid on the upper
+    /// hemisphere, uniform in cosine and azimuth so that all cells have the
+    /// same solid angle, by the midpoint rule with `m` x `m` points per cell.
+    fn cells(n: usize, m: usize, f: impl Fn(Vec3) -> f64) -> Vec<f64> {
+        let (dz, dphi) = (1. / (n * m) as f64, 2. * PI / (n * m) as f64);
+        let mut cells = vec![0.; n * n];
+        for i in 0..n * m {
+            for j in 0..n * m {
+                let w = direction((i as f64 + 0.5) * dz, (j as f64 + 0.5) * dphi);
+                cells[i / m * n + j / m] += f(w) * dz * dphi;
+            }
+        }
+        cells
+    }
+
+    /// The cell of the grid of `cells` that `w` falls into.
+    fn cell(n: usize, w: Vec3) -> usize {
+        let i = ((w.2 * n as f64) as usize).min(n - 1);
+        let phi = w.1.atan2(w.0).rem_euclid(2. * PI);
+        let j = ((phi / (2. * PI) * n as f64) as usize).min(n - 1);
+        i * n + j
+    }
+
+    #[test]
+    fn distribution_is_normalized() {
+        for &alpha in &[0.2, 0.5, 1.] {
+            let ggx = Ggx { alpha };
+            // Microfacets project onto exactly the macroscopic surface...
+            let projected: f64 = cells(1, 1000, |h| ggx.d(h) * h.2).iter().sum();
+            assert!((projected - 1.).abs() < 1e-3, "{}: {}", alpha, projected);
+            // ...and those visible from any direction onto its projection.
+            for &z in &[0.1, 0.6, 1.] {
+                let wo = direction(z, 0.3);
+                let visible: f64 = cells(1, 1000, |h| ggx.visible_d(wo, h)).iter().sum();
+                assert!((visible - 1.).abs() < 2e-3, "{} {}: {}", alpha, z, visible);
+            }
+        }
+        assert_eq!(Ggx { alpha: 0.3 }.g1(Vec3(0., 0., 1.)), 1.);
+    }
+
+    #[test]
+    fn samples_follow_visible_d() {
+        let mut rng = SmallRng::seed_from_u64(3);
+        let (n, samples) = (8, 200_000);
+        for &(alpha, z) in &[(0.3, 0.9), (0.6, 0.3), (1., 0.05)] {
+            let ggx = Ggx { alpha };
+            let wo = direction(z, 1.);
+            let mut histogram = vec![0.; n * n];
+            for _ in 0..samples {
+                let h = ggx.sample_visible(wo, rng.gen(), rng.gen());
+                assert!((h.length() - 1.).abs() < 1e-9 && h.2 >= 0.);
+                histogram[cell(n, h)] += 1. / samples as f64;
+            }
+            let expected = cells(n, 16, |h| ggx.visible_d(wo, h));
+            for (found, expected) in histogram.iter().zip(&expected) {
+                assert!(
+                    (found - expected).abs() < 5e-3,
+                    "{} {}: {} != {}",
+                    alpha,
+                    z,
+                    found,
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fresnel() {
+        // Head on, glass reflects 4%, whichever side the light comes from.
+        assert!((fresnel_dielectric(1., 1.5) - 0.04).abs() < 1e-12);
+        assert!((fresnel_dielectric(1., 1. / 1.5) - 0.04).abs() < 1e-12);
+        // Matching media don't reflect, and grazing light is all reflected.
+        assert!(fresnel_dielectric(0.3, 1.).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0., 1.5), 1.);
+        // Past the critical angle of about 41.8°, light stays inside.
+        assert_eq!(fresnel_dielectric(0.7, 1. / 1.5), 1.);
+        assert!(fresnel_dielectric(0.8, 1. / 1.5) < 1.);
+        for i in 1..10 {
+            let cos = i as f64 / 10.;
+            assert!(fresnel_dielectric(cos, 1.5) > fresnel_dielectric(cos + 0.1, 1.5));
+        }
+
+        // A conductor without extinction is a dielectric.
+        for i in 0..=10 {
+            let cos = i as f64 / 10.;
+            let conductor = fresnel_conductor(cos, Vec3(1.2, 1.5, 2.), Vec3::default());
+            let dielectric = Vec3(
+                fresnel_dielectric(cos, 1.2),
+                fresnel_dielectric(cos, 1.5),
+                fresnel_dielectric(cos, 2.),
+            );
+            assert!((conductor - dielectric).length() < 1e-9, "{}", cos);
+        }
+        // Head on, R = ((n - 1)² + k²) / ((n + 1)² + k²).
+        let (eta, k) = (Vec3(0.2, 0.9, 1.1), Vec3(3.9, 2.5, 2.1));
+        let expected = eta.zip_with(k, |n, k| {
+            ((n - 1.) * (n - 1.) + k * k) / ((n + 1.) * (n + 1.) + k * k)
+        });
+        assert!((fresnel_conductor(1., eta, k) - expected).length() < 1e-9);
+        assert!((fresnel_conductor(0., eta, k) - Vec3::from(1.)).length() < 1e-9);
+    }
+}
@@ -1,11 +1,18 @@
 use crate::{
     aabb::Aabb,
     objects::{HitRecord, Object},
+    scene_file::ObjectDesc,
     vec3::Vec3,
     Material, Ray,
 };
 use std::ops::Range;
 
+/// Distance past the entry point from which the exit through the boundary is
+/// looked for. It isn't the render's `ray_epsilon`: the entry is found over
+/// the whole line rather than from a surface, and a larger gap would let rays
+/// pass through thin media without finding an exit.
+const EXIT_EPSILON: f64 = 0.0001;
+
 /// A medium of constant density that scatters light internally, such as
 /// (greatly simplified) smoke or fog.
 #[derive(Debug, Clone)]
@@ -28,7 +35,7 @@ impl<O: Object> Object for ConstantMedium<O> {
         rng: &mut dyn FnMut() -> f64,
     ) -> Option<HitRecord<'o>> {
         if let Some(mut hit1) = self.boundary.hit(ray, f64::MIN..f64::MAX, rng) {
-            if let Some(mut hit2) = self.boundary.hit(ray, hit1.t + 0.0001..f64::MAX, rng) {
+            if let Some(mut hit2) = self.boundary.hit(ray, hit1.t + EXIT_EPSILON..f64::MAX, rng) {
                 hit1.t = hit1.t.max(t_range.start);
                 hit2.t = hit2.t.min(t_range.end);
                 if hit1.t >= hit2.t {
@@ -58,4 +65,16 @@ impl<O: Object> Object for ConstantMedium<O> {
     fn bounding_box(&self, exposure: Range<f64>) -> Aabb {
         self.boundary.bounding_box(exposure)
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Medium {
+            boundary: Box::new(self.boundary.describe()?),
+            density: self.density,
+            material: self.material.describe()?,
+        })
+    }
+
+    fn faces(&self) -> usize {
+        self.boundary.faces()
+    }
 }
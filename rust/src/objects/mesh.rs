@@ -0,0 +1,256 @@
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    loaders::LoadError,
+    objects::{
+        triangle::{hit_triangle, triangle_bounding_box},
+        HitRecord, Object, Triangle,
+    },
+    scene_file::ObjectDesc,
+    vec3::Vec3,
+    Material, Ray,
+};
+use std::{ops::Range, sync::Arc};
+
+/// Vertex and index buffers of a triangle mesh.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    /// Vertex positions.
+    pub positions: Vec<Vec3>,
+    /// Vertex normals, either empty for flat shading or one per position.
+    pub normals: Vec<Vec3>,
+    /// Vertex texture coordinates, either empty or one per position.
+    pub uvs: Vec<(f64, f64)>,
+    /// Indices into the vertex buffers of the corners of each triangle, in
+    /// counter-clockwise order seen from the front.
+    pub indices: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    /// The faces as separate triangles, such as for sampling an emissive mesh
+    /// as a light.
+    pub fn triangles<'a>(&'a self, material: &'a Material) -> impl Iterator<Item = Triangle> + 'a {
+        self.indices.iter().map(move |&[a, b, c]| Triangle {
+            vertices: [self.positions[a], self.positions[b], self.positions[c]],
+            normals: (!self.normals.is_empty())
+                .then(|| [self.normals[a], self.normals[b], self.normals[c]]),
+            uvs: (!self.uvs.is_empty()).then(|| [self.uvs[a], self.uvs[b], self.uvs[c]]),
+            material: material.clone(),
+        })
+    }
+}
+
+/// A triangle mesh sharing one material.
+///
+/// The triangles index into shared vertex buffers, and are kept in a BVH of
+/// their own, so a mesh goes into the scene as a single `Object`.
+#[derive(Debug)]
+pub struct Mesh {
+    faces: usize,
+    shared: Arc<Shared>,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    /// Builds the mesh, or returns an error if it has no faces, an index out
+    /// of range, attributes that don't match the positions or values that
+    /// aren't finite.
+    pub fn new(data: MeshData, material: Material) -> Result<Self, LoadError> {
+        let invalid = |message: &str| Err(LoadError::Invalid(message.to_string()));
+        if data.indices.is_empty() {
+            return invalid("mesh has no faces");
+        }
+        if !(data.normals.is_empty() || data.normals.len() == data.positions.len()) {
+            return invalid("mesh must have no normals or one per vertex");
+        }
+        if !(data.uvs.is_empty() || data.uvs.len() == data.positions.len()) {
+            return invalid("mesh must have no texture coordinates or one per vertex");
+        }
+        if !data
+            .indices
+            .iter()
+            .flatten()
+            .all(|&i| i < data.positions.len())
+        {
+            return invalid("mesh index out of range");
+        }
+        let finite = data
+            .positions
+            .iter()
+            .chain(&data.normals)
+            .all(|v| v.is_finite())
+            && data.uvs.iter().all(|(u, v)| u.is_finite() && v.is_finite());
+        if !finite {
+            return invalid("mesh has a vertex attribute that isn't finite");
+        }
+
+        let faces = data.indices.len();
+        let shared = Arc::new(Shared { data, material });
+        let objects = (0..faces)
+            .map(|index| {
+                Box::new(Face {
+                    mesh: Arc::clone(&shared),
+                    index,
+                }) as Box<dyn Object>
+            })
+            .collect();
+
+        Ok(Mesh {
+            faces,
+            shared,
+            bvh: Bvh::new(objects, 0. ..1.),
+        })
+    }
+
+    /// The vertex and index buffers of the mesh.
+    pub fn data(&self) -> &MeshData {
+        &self.shared.data
+    }
+}
+
+impl Object for Mesh {
+    #[inline]
+    fn hit<'o>(
+        &'o self,
+        ray: &Ray,
+        t_range: Range<f64>,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Option<HitRecord<'o>> {
+        self.bvh.hit(ray, t_range, rng)
+    }
+
+    fn bounding_box(&self, exposure: Range<f64>) -> Aabb {
+        self.bvh.bounding_box(exposure)
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        let data = &self.shared.data;
+        Some(ObjectDesc::Mesh {
+            positions: data.positions.clone(),
+            normals: data.normals.clone(),
+            uvs: data.uvs.clone(),
+            indices: data.indices.clone(),
+            material: self.shared.material.describe()?,
+        })
+    }
+
+    fn faces(&self) -> usize {
+        self.faces
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    data: MeshData,
+    material: Material,
+}
+
+/// One triangle of a `Mesh`.
+#[derive(Debug)]
+struct Face {
+    mesh: Arc<Shared>,
+    index: usize,
+}
+
+impl Face {
+    fn vertices(&self) -> [Vec3; 3] {
+        let [a, b, c] = self.mesh.data.indices[self.index];
+        let positions = &self.mesh.data.positions;
+        [positions[a], positions[b], positions[c]]
+    }
+}
+
+impl Object for Face {
+    #[inline]
+    fn hit<'o>(
+        &'o self,
+        ray: &Ray,
+        t_range: Range<f64>,
+        _rng: &mut dyn FnMut() -> f64,
+    ) -> Option<HitRecord<'o>> {
+        let data = &self.mesh.data;
+        let [a, b, c] = data.indices[self.index];
+        let normals = if data.normals.is_empty() {
+            None
+        } else {
+            Some([data.normals[a], data.normals[b], data.normals[c]])
+        };
+        let uvs = if data.uvs.is_empty() {
+            None
+        } else {
+            Some([data.uvs[a], data.uvs[b], data.uvs[c]])
+        };
+        hit_triangle(
+            &self.vertices(),
+            normals,
+            uvs,
+            &self.mesh.material,
+            ray,
+            t_range,
+        )
+    }
+
+    fn bounding_box(&self, _exposure: Range<f64>) -> Aabb {
+        triangle_bounding_box(&self.vertices())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture;
+
+    fn material() -> Material {
+        Material::Lambertian {
+            albedo: texture::constant(Vec3::from(0.5)),
+        }
+    }
+
+    /// A unit square in the XY plane, split along its diagonal.
+    fn square() -> MeshData {
+        MeshData {
+            positions: vec![
+                Vec3(0., 0., 0.),
+                Vec3(1., 0., 0.),
+                Vec3(1., 1., 0.),
+                Vec3(0., 1., 0.),
+            ],
+            normals: vec![],
+            uvs: vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)],
+            indices: vec![[0, 1, 2], [0, 2, 3]],
+        }
+    }
+
+    #[test]
+    fn hits_both_faces() {
+        let mesh = Mesh::new(square(), material()).unwrap();
+        assert_eq!(mesh.faces(), 2);
+        for &(x, y) in &[(0.7, 0.2), (0.2, 0.7)] {
+            let ray = Ray::new(Vec3(x, y, 1.), Vec3(0., 0., -1.), 0.);
+            let hit = mesh.hit(&ray, 0.001..f64::MAX, &mut || 0.).unwrap();
+            assert!((hit.t - 1.).abs() < 1e-12);
+            // The shared texture coordinates match the positions.
+            assert!((hit.u - x).abs() < 1e-12 && (hit.v - y).abs() < 1e-12);
+        }
+        let ray = Ray::new(Vec3(1.5, 0.5, 1.), Vec3(0., 0., -1.), 0.);
+        assert!(mesh.hit(&ray, 0.001..f64::MAX, &mut || 0.).is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        let cases: Vec<fn(&mut MeshData)> = vec![
+            |data| data.indices.clear(),
+            |data| data.indices[1][2] = 4,
+            |data| data.normals = vec![Vec3(0., 0., 1.)],
+            |data| data.uvs.truncate(3),
+            |data| data.positions[2].0 = f64::NAN,
+            |data| data.positions[3].1 = f64::INFINITY,
+            |data| data.uvs[0].1 = f64::NAN,
+        ];
+        for (i, case) in cases.into_iter().enumerate() {
+            let mut data = square();
+            case(&mut data);
+            assert!(Mesh::new(data, material()).is_err(), "case {}", i);
+        }
+    }
+}
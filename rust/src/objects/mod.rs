@@ -1,13 +1,19 @@
 mod constant_medium;
+mod mesh;
 mod object;
 mod prism;
 mod rect;
 mod sphere;
 mod transformation;
+mod triangle;
 
 pub use constant_medium::ConstantMedium;
+pub use mesh::{Mesh, MeshData};
+pub(crate) use object::PDF_EPSILON;
 pub use object::{HitRecord, Object, PdfObject};
-pub use prism::rect_prism;
+pub use prism::{rect_prism, Prism};
 pub use rect::{Rect, StaticAxis, StaticX, StaticY, StaticZ};
+pub(crate) use sphere::get_sphere_uv;
 pub use sphere::Sphere;
 pub use transformation::{rotate_y, And, FlipNormals, LinearMove, RotateY, Scale, Translate};
+pub use triangle::Triangle;
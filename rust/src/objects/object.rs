@@ -1,6 +1,6 @@
 use std::ops::Range;
 
-use crate::{aabb::Aabb, material::Material, ray::Ray, vec3::Vec3};
+use crate::{aabb::Aabb, material::Material, ray::Ray, scene_file::ObjectDesc, vec3::Vec3};
 
 /// A description of a `Ray` hitting an `Object`. This stores information needed
 /// for rendering later.
@@ -23,7 +23,17 @@ pub struct HitRecord<'m> {
     pub material: &'m Material,
 }
 
-pub trait PdfObject {
+/// Distance before which `pdf_value` ignores hits. `pdf_value` doesn't see
+/// the `RenderSettings`, so this is the default `ray_epsilon`: the directions
+/// it weighs leave a surface just like the rays the integrators trace.
+pub(crate) const PDF_EPSILON: f64 = 0.001;
+
+/// An object that can be sampled directly, such as a light source.
+///
+/// `random` picks a direction from `origin` towards a point on the object, and
+/// `pdf_value` gives the density (with respect to solid angle) of picking the
+/// direction `v` from `origin` that way.
+pub trait PdfObject: Sync + Send {
     fn pdf_value(&self, _origin: Vec3, _v: Vec3, _rng: &mut dyn FnMut() -> f64) -> f64 {
         0.0
     }
@@ -61,6 +71,18 @@ pub trait Object: std::fmt::Debug + Sync + Send {
     /// This is called during scene setup, not rendering, and so it may be
     /// expensive.
     fn bounding_box(&self, exposure: Range<f64>) -> Aabb;
+
+    /// Describes the object for a scene file, or returns `None` if it can't be
+    /// described, like objects defined outside this crate.
+    fn describe(&self) -> Option<ObjectDesc> {
+        None
+    }
+
+    /// Number of triangles making up the object, for reporting the size of
+    /// loaded meshes. Other shapes don't count.
+    fn faces(&self) -> usize {
+        0
+    }
 }
 
 impl Object for Box<dyn Object> {
@@ -75,4 +97,42 @@ impl Object for Box<dyn Object> {
     fn bounding_box(&self, exposure: Range<f64>) -> Aabb {
         (**self).bounding_box(exposure)
     }
+    fn describe(&self) -> Option<ObjectDesc> {
+        (**self).describe()
+    }
+    fn faces(&self) -> usize {
+        (**self).faces()
+    }
+}
+
+impl<T: PdfObject + ?Sized> PdfObject for &T {
+    fn pdf_value(&self, origin: Vec3, v: Vec3, rng: &mut dyn FnMut() -> f64) -> f64 {
+        (**self).pdf_value(origin, v, rng)
+    }
+    fn random(&self, origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
+        (**self).random(origin, rng)
+    }
+}
+
+impl PdfObject for Box<dyn PdfObject> {
+    fn pdf_value(&self, origin: Vec3, v: Vec3, rng: &mut dyn FnMut() -> f64) -> f64 {
+        (**self).pdf_value(origin, v, rng)
+    }
+    fn random(&self, origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
+        (**self).random(origin, rng)
+    }
+}
+
+/// Samples each of the objects with equal probability.
+impl PdfObject for [Box<dyn PdfObject>] {
+    fn pdf_value(&self, origin: Vec3, v: Vec3, rng: &mut dyn FnMut() -> f64) -> f64 {
+        let weight = 1. / self.len() as f64;
+        self.iter()
+            .map(|obj| weight * obj.pdf_value(origin, v, rng))
+            .sum()
+    }
+    fn random(&self, origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
+        let idx = (rng(0., self.len() as f64) as usize).min(self.len() - 1);
+        self[idx].random(origin, rng)
+    }
 }
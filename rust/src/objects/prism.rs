@@ -1,11 +1,54 @@
 use crate::{
-    objects::{And, FlipNormals, Object, Rect, StaticX, StaticY, StaticZ},
+    aabb::Aabb,
+    objects::{And, FlipNormals, HitRecord, Object, Rect, StaticX, StaticY, StaticZ},
+    scene_file::ObjectDesc,
     vec3::{Axis::*, Vec3},
-    Material,
+    Material, Ray,
 };
+use std::ops::Range;
+
+type Sides = And<
+    And<Rect<StaticZ>, And<Rect<StaticY>, Rect<StaticX>>>,
+    And<FlipNormals<Rect<StaticZ>>, And<FlipNormals<Rect<StaticY>>, FlipNormals<Rect<StaticX>>>>,
+>;
+
+/// An axis-aligned rectangular prism, made of six `Rect`s.
+///
+/// Use the `rect_prism` function to obtain one of these.
+#[derive(Debug)]
+pub struct Prism {
+    min: Vec3,
+    max: Vec3,
+    sides: Sides,
+}
+
+impl Object for Prism {
+    #[inline]
+    fn hit<'o>(
+        &'o self,
+        ray: &Ray,
+        t_range: Range<f64>,
+        rng: &mut dyn FnMut() -> f64,
+    ) -> Option<HitRecord<'o>> {
+        self.sides.hit(ray, t_range, rng)
+    }
+
+    fn bounding_box(&self, exposure: Range<f64>) -> Aabb {
+        self.sides.bounding_box(exposure)
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Prism {
+            min: self.min,
+            max: self.max,
+            material: (self.sides.0).0.material.describe()?,
+        })
+    }
+}
+
 /// Generates a rectangular prism having min and max corners `p0` and `p1`.
-pub fn rect_prism(p0: Vec3, p1: Vec3, material: Material) -> impl Object {
-    And(
+pub fn rect_prism(p0: Vec3, p1: Vec3, material: Material) -> Prism {
+    let sides = And(
         And(
             Rect {
                 orthogonal_to: StaticZ,
@@ -56,5 +99,10 @@ pub fn rect_prism(p0: Vec3, p1: Vec3, material: Material) -> impl Object {
                 }),
             ),
         ),
-    )
+    );
+    Prism {
+        min: p0,
+        max: p1,
+        sides,
+    }
 }
@@ -1,6 +1,7 @@
 use crate::{
     aabb::Aabb,
-    objects::{HitRecord, Object, PdfObject},
+    objects::{HitRecord, Object, PdfObject, PDF_EPSILON},
+    scene_file::{AxisDesc, ObjectDesc},
     vec3::{
         Axis::{self, *},
         Vec3,
@@ -126,14 +127,28 @@ impl<A: StaticAxis> Object for Rect<A> {
 
         Aabb { min, max }
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Rect {
+            axis: match A::AXIS {
+                X => AxisDesc::X,
+                Y => AxisDesc::Y,
+                Z => AxisDesc::Z,
+            },
+            range0: (self.range0.start, self.range0.end),
+            range1: (self.range1.start, self.range1.end),
+            k: self.k,
+            material: self.material.describe()?,
+        })
+    }
 }
 
 impl<A: StaticAxis> PdfObject for Rect<A> {
     fn pdf_value(&self, origin: Vec3, v: Vec3, rng: &mut dyn FnMut() -> f64) -> f64 {
         let sample_ray = Ray::new(origin, v, 0.0);
-        if let Some(hit) = self.hit(&sample_ray, 0.001..f64::MAX, &mut || rng()) {
+        if let Some(hit) = self.hit(&sample_ray, PDF_EPSILON..f64::MAX, &mut || rng()) {
             let area =
-                (self.range1.end - self.range1.start) / (self.range0.end - self.range0.start);
+                (self.range0.end - self.range0.start) * (self.range1.end - self.range1.start);
             let distance_squared = hit.t * hit.t * v.dot(v);
             let cosine = v.dot(hit.normal).abs() / v.length();
 
@@ -143,11 +158,10 @@ impl<A: StaticAxis> PdfObject for Rect<A> {
         }
     }
     fn random(&self, origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
-        let random_point = Vec3(
-            rng(self.range1.start, self.range1.end),
-            self.k,
-            rng(self.range0.start, self.range0.end),
-        );
+        let mut random_point = Vec3::default();
+        random_point[A::AXIS] = self.k;
+        random_point[A::OTHER1] = rng(self.range0.start, self.range0.end);
+        random_point[A::OTHER2] = rng(self.range1.start, self.range1.end);
         random_point - origin
     }
 }
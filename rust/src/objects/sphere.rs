@@ -1,6 +1,7 @@
 use crate::{
     aabb::Aabb,
     objects::{HitRecord, Object},
+    scene_file::ObjectDesc,
     vec3::{Axis::*, Vec3},
     Material, Ray,
 };
@@ -17,7 +18,8 @@ pub struct Sphere {
     pub material: Material,
 }
 
-fn get_sphere_uv(p: Vec3) -> (f64, f64) {
+/// Texture coordinates of the point `p` on the unit sphere.
+pub(crate) fn get_sphere_uv(p: Vec3) -> (f64, f64) {
     use std::f64::consts::PI;
     let phi = f64::atan2(p[Z], p[X]);
     let theta = p[Y].asin();
@@ -68,4 +70,12 @@ impl Object for Sphere {
             max: Vec3::from(self.radius) + self.center,
         }
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Sphere {
+            center: self.center,
+            radius: self.radius,
+            material: self.material.describe()?,
+        })
+    }
 }
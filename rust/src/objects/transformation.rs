@@ -1,6 +1,7 @@
 use crate::{
     aabb::Aabb,
-    objects::{HitRecord, Object},
+    objects::{HitRecord, Object, PdfObject},
+    scene_file::ObjectDesc,
     vec3::Vec3,
     Ray,
 };
@@ -28,6 +29,23 @@ impl<O: Object> Object for FlipNormals<O> {
     fn bounding_box(&self, exposure: Range<f64>) -> Aabb {
         self.0.bounding_box(exposure)
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::FlipNormals(Box::new(self.0.describe()?)))
+    }
+
+    fn faces(&self) -> usize {
+        self.0.faces()
+    }
+}
+
+impl<O: PdfObject> PdfObject for FlipNormals<O> {
+    fn pdf_value(&self, origin: Vec3, v: Vec3, rng: &mut dyn FnMut() -> f64) -> f64 {
+        self.0.pdf_value(origin, v, rng)
+    }
+    fn random(&self, origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
+        self.0.random(origin, rng)
+    }
 }
 
 /// The same geometry as `O`, but translated by `offset` from the origin.
@@ -62,6 +80,17 @@ impl<T: Object> Object for Translate<T> {
             max: b.max + self.offset,
         }
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Translate {
+            offset: self.offset,
+            object: Box::new(self.object.describe()?),
+        })
+    }
+
+    fn faces(&self) -> usize {
+        self.object.faces()
+    }
 }
 
 /// The same geometry as `O`, but scaled by `factor` on each axis.
@@ -98,6 +127,17 @@ impl<T: Object> Object for Scale<T> {
             max: b.max * self.factor,
         }
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Scale {
+            factor: self.factor,
+            object: Box::new(self.object.describe()?),
+        })
+    }
+
+    fn faces(&self) -> usize {
+        self.object.faces()
+    }
 }
 
 /// The same geometry as `O`, but rotated around the Y axis.
@@ -160,6 +200,17 @@ impl<T: Object> Object for RotateY<T> {
         );
         Aabb { min, max }
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::RotateY {
+            degrees: self.sin_theta.atan2(self.cos_theta) * 180. / std::f64::consts::PI,
+            object: Box::new(self.object.describe()?),
+        })
+    }
+
+    fn faces(&self) -> usize {
+        self.object.faces()
+    }
 }
 
 /// Combines both `T` and `S` into one `Object`.
@@ -187,6 +238,17 @@ impl<T: Object, S: Object> Object for And<T, S> {
             .bounding_box(exposure.clone())
             .merge(self.1.bounding_box(exposure))
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Group(vec![
+            self.0.describe()?,
+            self.1.describe()?,
+        ]))
+    }
+
+    fn faces(&self) -> usize {
+        self.0.faces() + self.1.faces()
+    }
 }
 
 /// Returns a version of `object` that has been rotated `degrees` around the Y
@@ -242,4 +304,15 @@ impl<O: Object> Object for LinearMove<O> {
 
         bb_start.merge(bb_end)
     }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::LinearMove {
+            motion: self.motion,
+            object: Box::new(self.object.describe()?),
+        })
+    }
+
+    fn faces(&self) -> usize {
+        self.object.faces()
+    }
 }
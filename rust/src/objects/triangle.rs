@@ -0,0 +1,251 @@
+use crate::{
+    aabb::Aabb,
+    objects::{HitRecord, Object, PdfObject, PDF_EPSILON},
+    scene_file::ObjectDesc,
+    vec3::Vec3,
+    Material, Ray,
+};
+use std::ops::Range;
+
+/// A triangle.
+///
+/// The front of the triangle is the side from which its vertices appear in
+/// counter-clockwise order.
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    /// Positions of the corners.
+    pub vertices: [Vec3; 3],
+    /// Normals at the corners, interpolated across the triangle for smooth
+    /// shading. Without them, the triangle is flat shaded.
+    pub normals: Option<[Vec3; 3]>,
+    /// Texture coordinates at the corners. Without them, the barycentric
+    /// coordinates of the hit are used.
+    pub uvs: Option<[(f64, f64); 3]>,
+    /// Material of the triangle.
+    pub material: Material,
+}
+
+/// Intersects `ray` with a triangle using the [Möller–Trumbore][mt]
+/// algorithm, returning `t` and the barycentric weights of the second and
+/// third vertices.
+///
+/// [mt]: https://en.wikipedia.org/wiki/M%C3%B6ller%E2%80%93Trumbore_intersection_algorithm
+#[inline]
+fn intersect(vertices: &[Vec3; 3], ray: &Ray, t_range: Range<f64>) -> Option<(f64, f64, f64)> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+
+    let pvec = ray.direction.cross(&edge2);
+    let det = edge1.dot(pvec);
+    // The ray is parallel to the triangle.
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1. / det;
+
+    let tvec = ray.origin - vertices[0];
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0. ..=1.).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = ray.direction.dot(qvec) * inv_det;
+    if b2 < 0. || b1 + b2 > 1. {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if t_range.start <= t && t < t_range.end {
+        Some((t, b1, b2))
+    } else {
+        None
+    }
+}
+
+/// Shared by `Triangle` and the faces of `Mesh`.
+#[inline]
+pub(crate) fn hit_triangle<'o>(
+    vertices: &[Vec3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: &'o Material,
+    ray: &Ray,
+    t_range: Range<f64>,
+) -> Option<HitRecord<'o>> {
+    let (t, b1, b2) = intersect(vertices, ray, t_range)?;
+    let b0 = 1. - b1 - b2;
+
+    let normal = match normals {
+        Some(n) => b0 * n[0] + b1 * n[1] + b2 * n[2],
+        None => (vertices[1] - vertices[0]).cross(&(vertices[2] - vertices[0])),
+    }
+    .into_unit();
+
+    let (u, v) = match uvs {
+        Some(uv) => (
+            b0 * uv[0].0 + b1 * uv[1].0 + b2 * uv[2].0,
+            b0 * uv[0].1 + b1 * uv[1].1 + b2 * uv[2].1,
+        ),
+        None => (b1, b2),
+    };
+
+    Some(HitRecord {
+        t,
+        p: ray.point_at_parameter(t),
+        u,
+        v,
+        normal,
+        material,
+    })
+}
+
+/// Bounding box of a triangle, padded so that axis-aligned triangles don't
+/// produce a flat box.
+pub(crate) fn triangle_bounding_box(vertices: &[Vec3; 3]) -> Aabb {
+    let min = vertices[0].zip_with3(vertices[1], vertices[2], |a, b, c| a.min(b).min(c));
+    let max = vertices[0].zip_with3(vertices[1], vertices[2], |a, b, c| a.max(b).max(c));
+    Aabb {
+        min: min - Vec3::from(0.0001),
+        max: max + Vec3::from(0.0001),
+    }
+}
+
+impl Object for Triangle {
+    #[inline]
+    fn hit<'o>(
+        &'o self,
+        ray: &Ray,
+        t_range: Range<f64>,
+        _rng: &mut dyn FnMut() -> f64,
+    ) -> Option<HitRecord<'o>> {
+        hit_triangle(
+            &self.vertices,
+            self.normals,
+            self.uvs,
+            &self.material,
+            ray,
+            t_range,
+        )
+    }
+
+    fn bounding_box(&self, _exposure: Range<f64>) -> Aabb {
+        triangle_bounding_box(&self.vertices)
+    }
+
+    fn describe(&self) -> Option<ObjectDesc> {
+        Some(ObjectDesc::Triangle {
+            vertices: self.vertices,
+            normals: self.normals,
+            uvs: self.uvs,
+            material: self.material.describe()?,
+        })
+    }
+
+    fn faces(&self) -> usize {
+        1
+    }
+}
+
+impl PdfObject for Triangle {
+    fn pdf_value(&self, origin: Vec3, v: Vec3, _rng: &mut dyn FnMut() -> f64) -> f64 {
+        let sample_ray = Ray::new(origin, v, 0.0);
+        if let Some((t, _, _)) = intersect(&self.vertices, &sample_ray, PDF_EPSILON..f64::MAX) {
+            let cross =
+                (self.vertices[1] - self.vertices[0]).cross(&(self.vertices[2] - self.vertices[0]));
+            let area = 0.5 * cross.length();
+            let distance_squared = t * t * v.dot(v);
+            let cosine = v.dot(cross).abs() / (v.length() * cross.length());
+
+            distance_squared / (cosine * area)
+        } else {
+            0.0
+        }
+    }
+
+    fn random(&self, origin: Vec3, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
+        // Uniform over the area of the triangle.
+        let sqrt_r1 = rng(0., 1.).sqrt();
+        let r2 = rng(0., 1.);
+        let [p0, p1, p2] = self.vertices;
+        let point = (1. - sqrt_r1) * p0 + sqrt_r1 * (1. - r2) * p1 + sqrt_r1 * r2 * p2;
+        point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture;
+
+    fn triangle() -> Triangle {
+        Triangle {
+            vertices: [Vec3(0., 0., 0.), Vec3(1., 0., 0.), Vec3(0., 1., 0.)],
+            normals: None,
+            uvs: None,
+            material: Material::Lambertian {
+                albedo: texture::constant(Vec3::from(0.5)),
+            },
+        }
+    }
+
+    /// Casts a ray down the Z axis through (`x`, `y`) at the triangle.
+    fn cast(triangle: &Triangle, x: f64, y: f64) -> Option<HitRecord<'_>> {
+        let ray = Ray::new(Vec3(x, y, 1.), Vec3(0., 0., -1.), 0.);
+        triangle.hit(&ray, 0.001..f64::MAX, &mut || 0.)
+    }
+
+    #[test]
+    fn hit_and_miss() {
+        let triangle = triangle();
+        let hit = cast(&triangle, 0.25, 0.25).unwrap();
+        assert!((hit.t - 1.).abs() < 1e-12);
+        assert!((hit.p - Vec3(0.25, 0.25, 0.)).length() < 1e-12);
+        // Counter-clockwise seen from +Z, so the flat normal faces +Z.
+        assert!((hit.normal - Vec3(0., 0., 1.)).length() < 1e-12);
+
+        assert!(cast(&triangle, 0.75, 0.75).is_none());
+        assert!(cast(&triangle, -0.1, 0.5).is_none());
+        // Behind the ray, and parallel to it.
+        assert!(triangle
+            .hit(
+                &Ray::new(Vec3(0.25, 0.25, 1.), Vec3(0., 0., 1.), 0.),
+                0.001..f64::MAX,
+                &mut || 0.
+            )
+            .is_none());
+        assert!(triangle
+            .hit(
+                &Ray::new(Vec3(-1., 0.25, 0.), Vec3(1., 0., 0.), 0.),
+                0.001..f64::MAX,
+                &mut || 0.
+            )
+            .is_none());
+        // Outside of the range.
+        let ray = Ray::new(Vec3(0.25, 0.25, 1.), Vec3(0., 0., -1.), 0.);
+        assert!(triangle.hit(&ray, 0.001..0.5, &mut || 0.).is_none());
+    }
+
+    #[test]
+    fn barycentric_uvs() {
+        let mut triangle = triangle();
+        // Without texture coordinates, the weights of the second and third
+        // vertices are used.
+        let hit = cast(&triangle, 0.2, 0.3).unwrap();
+        assert!((hit.u - 0.2).abs() < 1e-12 && (hit.v - 0.3).abs() < 1e-12);
+
+        triangle.uvs = Some([(0.5, 0.5), (1., 0.5), (0.5, 1.)]);
+        let hit = cast(&triangle, 0.2, 0.3).unwrap();
+        assert!((hit.u - 0.6).abs() < 1e-12 && (hit.v - 0.65).abs() < 1e-12);
+    }
+
+    #[test]
+    fn interpolated_normals() {
+        let mut triangle = triangle();
+        triangle.normals = Some([Vec3(0., 0., 1.), Vec3(1., 0., 0.), Vec3(0., 1., 0.)]);
+        let hit = cast(&triangle, 0.5, 0.).unwrap();
+        assert!((hit.normal - Vec3(1., 0., 1.).into_unit()).length() < 1e-12);
+        let hit = cast(&triangle, 0., 0.).unwrap();
+        assert!((hit.normal - Vec3(0., 0., 1.)).length() < 1e-12);
+    }
+}
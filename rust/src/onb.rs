@@ -22,6 +22,12 @@ impl Onb {
     pub fn local(&self, a: Vec3) -> Vec3 {
         a[X] * self[U] + a[Y] * self[V] + a[Z] * self[W]
     }
+
+    /// Returns the coordinates of `a` in the current basis; the inverse of
+    /// `local`.
+    pub fn coords(&self, a: Vec3) -> Vec3 {
+        Vec3(a.dot(self[U]), a.dot(self[V]), a.dot(self[W]))
+    }
 }
 
 /// Names for Onb lanes when used as a coordinate, exactly the same like
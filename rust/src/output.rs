@@ -0,0 +1,131 @@
+//! Writers for saving rendered images.
+//!
+//! The high dynamic range formats here store the linear radiance of the image
+//! as is, while the 8-bit formats go through a `DisplayTransform`.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::{exr, tonemap::DisplayTransform, Image};
+
+impl Image {
+    /// Saves the image to `path` with the default `DisplayTransform`; see
+    /// `save_with`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        self.save_with(path, &DisplayTransform::default())
+    }
+
+    /// Saves the image to `path`, picking the format by the file extension:
+    ///
+    /// - `png`, `jpg`/`jpeg` and `ppm` (binary P6) for display, through the
+    ///   `image` crate, after applying `display`;
+    /// - `pfm`, `hdr` and `exr` for the raw linear radiance, with
+    ///   `write_pfm`, `write_hdr` and `write_exr`.
+    pub fn save_with<P: AsRef<Path>>(
+        &self,
+        path: P,
+        display: &DisplayTransform,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("png") | Some("jpg") | Some("jpeg") | Some("ppm") => {
+                let mut buffer = image::RgbImage::new(self.width() as u32, self.height() as u32);
+                for (y, row) in self.rows().enumerate() {
+                    for (x, col) in row.iter().enumerate() {
+                        buffer.put_pixel(x as u32, y as u32, image::Rgb(display.to_rgb8(*col)));
+                    }
+                }
+                buffer.save(path)?;
+                Ok(())
+            }
+            Some("pfm") => write_pfm(self, path),
+            Some("hdr") => write_hdr(self, path),
+            Some("exr") => write_exr(self, path),
+            _ => Err("unsupported image format; use png, jpg, ppm, pfm, hdr or exr".into()),
+        }
+    }
+}
+
+/// Writes `image` as a little-endian [Portable Float Map][pfm].
+///
+/// [pfm]: http://www.pauldebevec.com/Research/HDR/PFM/
+pub fn write_pfm<P: AsRef<Path>>(image: &Image, path: P) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    // A negative scale marks the data as little-endian.
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+    // Scanlines are stored from the bottom up.
+    for row in image.rows().rev() {
+        for col in row {
+            for c in &[col.0, col.1, col.2] {
+                out.write_all(&(*c as f32).to_le_bytes())?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes `image` as a [Radiance RGBE][rgbe] `.hdr` image.
+///
+/// [rgbe]: https://en.wikipedia.org/wiki/RGBE_image_format
+pub fn write_hdr<P: AsRef<Path>>(image: &Image, path: P) -> Result<(), Box<dyn Error>> {
+    let out = BufWriter::new(File::create(path)?);
+    let pixels: Vec<_> = image
+        .rows()
+        .flatten()
+        .map(|col| image::Rgb([col.0 as f32, col.1 as f32, col.2 as f32]))
+        .collect();
+    image::codecs::hdr::HdrEncoder::new(out).encode(&pixels, image.width(), image.height())?;
+    Ok(())
+}
+
+/// Writes `image` as an uncompressed OpenEXR image with 32-bit float `R`, `G`
+/// and `B` channels.
+pub fn write_exr<P: AsRef<Path>>(image: &Image, path: P) -> Result<(), Box<dyn Error>> {
+    let mut out = BufWriter::new(File::create(path)?);
+    exr::write(&mut out, image)?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn pfm_layout() {
+        let image = Image::compute(2, 2, |x, y| Vec3(x as f64, y as f64, 0.25));
+        let path = std::env::temp_dir().join(format!("raytrace-{}.pfm", std::process::id()));
+        write_pfm(&image, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        // The bottom row, where `compute` starts counting `y`, comes first.
+        assert_eq!(
+            floats,
+            [0., 0., 0.25, 1., 0., 0.25, 0., 1., 0.25, 1., 1., 0.25]
+        );
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let image = Image::compute(1, 1, |_, _| Vec3::default());
+        assert!(image.save("out.bmp").is_err());
+    }
+}
@@ -16,39 +16,35 @@ pub fn random_cosine_dir(rng: &mut dyn FnMut() -> f64) -> Vec3 {
     Vec3(x, y, z)
 }
 
-pub enum Pdf {
+/// Probability density function over directions, used to importance sample
+/// scattered rays.
+///
+/// The `'a` lifetime refers to the objects being sampled by the `Hittable`
+/// variant, and to the component densities of a `Mixture`.
+pub enum Pdf<'a> {
     Cosine { uvw: Onb },
-    Hittable(Inner),
-    Mixture { p: Inner, q: Inner },
+    Hittable(Inner<'a>),
+    Mixture { p: &'a Pdf<'a>, q: &'a Pdf<'a> },
 }
 
-pub struct Inner {
+pub struct Inner<'a> {
     origin: Vec3,
-    hittable: Box<dyn PdfObject>,
+    hittable: &'a dyn PdfObject,
 }
 
-impl Inner {
-    fn value(&self, origin: Vec3, direction: Vec3, rng: &mut dyn FnMut() -> f64) -> f64 {
-        self.hittable.pdf_value(origin, direction, rng)
-    }
-
-    fn generate(&self, rng: &mut dyn FnMut(f64, f64) -> f64) -> Vec3 {
-        self.hittable.random(self.origin, rng)
-    }
-}
-
-impl Pdf {
+impl<'a> Pdf<'a> {
     pub fn cosine(w: Vec3) -> Self {
         Pdf::Cosine {
             uvw: Onb::build_from_w(w),
         }
     }
 
-    pub fn hittable(hittable: Box<dyn PdfObject>, origin: Vec3) -> Self {
+    pub fn hittable(hittable: &'a dyn PdfObject, origin: Vec3) -> Self {
         Pdf::Hittable(Inner { origin, hittable })
     }
 
-    pub fn mixture(p: Inner, q: Inner) -> Self {
+    /// Equal-weighted mixture of the `p` and `q` densities.
+    pub fn mixture(p: &'a Pdf<'a>, q: &'a Pdf<'a>) -> Self {
         Pdf::Mixture { p, q }
     }
 
@@ -59,16 +55,13 @@ impl Pdf {
                 if cosine > 0.0 {
                     cosine / PI
                 } else {
-                    // FIXME: Or 0.0?
                     0.0
                 }
             }
             Pdf::Hittable(Inner { origin, hittable }) => {
                 hittable.pdf_value(*origin, direction, rng)
             }
-            Pdf::Mixture { p, q } => {
-                0.5 * p.value(p.origin, direction, rng) + 0.5 * q.value(q.origin, direction, rng)
-            }
+            Pdf::Mixture { p, q } => 0.5 * p.value(direction, rng) + 0.5 * q.value(direction, rng),
         }
     }
 
@@ -1,4 +1,5 @@
-use rand::prelude::*;
+use rand::{prelude::*, rngs::SmallRng};
+use std::sync::Arc;
 
 use crate::vec3::Vec3;
 
@@ -17,11 +18,71 @@ fn generate_vecs(rng: &mut impl Rng) -> Vec<Vec3> {
     (0..=SIZE).map(|_| Vec3::in_unit_sphere(rng)).collect()
 }
 
+/// Seed of the tables shared by textures that weren't given their own.
+pub const DEFAULT_SEED: u64 = 0x5EED;
+
 lazy_static::lazy_static! {
-    pub static ref VECS: Vec<Vec3> = generate_vecs(&mut thread_rng());
-    pub static ref PERM_X: Vec<u8> = generate_perm(&mut thread_rng());
-    pub static ref PERM_Y: Vec<u8> = generate_perm(&mut thread_rng());
-    pub static ref PERM_Z: Vec<u8> = generate_perm(&mut thread_rng());
+    static ref DEFAULT: Arc<Perlin> = Arc::new(Perlin::new(DEFAULT_SEED));
+}
+
+/// Gradient and permutation tables of a Perlin noise function, generated from
+/// a seed so that renders using noise are reproducible.
+pub struct Perlin {
+    seed: u64,
+    vecs: Vec<Vec3>,
+    perm_x: Vec<u8>,
+    perm_y: Vec<u8>,
+    perm_z: Vec<u8>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        Perlin {
+            seed,
+            vecs: generate_vecs(&mut rng),
+            perm_x: generate_perm(&mut rng),
+            perm_y: generate_perm(&mut rng),
+            perm_z: generate_perm(&mut rng),
+        }
+    }
+
+    /// The tables generated from `DEFAULT_SEED`.
+    pub fn shared() -> Arc<Perlin> {
+        DEFAULT.clone()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn noise(&self, p: Vec3) -> f64 {
+        let ijk = p.map(f64::floor);
+        let uvw = p - ijk;
+        let mut corners = [[[Vec3::default(); 2]; 2]; 2];
+        for (idx, corner) in corners.iter_mut().flatten().flatten().enumerate() {
+            let (di, dj, dk) = (idx & 1, (idx & 2) / 2, (idx & 4) / 4);
+            let ix = self.perm_x[((ijk.0 as i32 + di as i32) & 255) as usize];
+            let iy = self.perm_y[((ijk.1 as i32 + dj as i32) & 255) as usize];
+            let iz = self.perm_z[((ijk.2 as i32 + dk as i32) & 255) as usize];
+            *corner = self.vecs[(ix ^ iy ^ iz) as usize];
+        }
+        trilinear_interp_array(&corners, uvw)
+    }
+
+    pub fn turb(&self, p: Vec3, depth: usize) -> f64 {
+        (1..depth + 1)
+            .map(|i| (1. / i as f64, p * i as f64))
+            .fold(0., |accum, (weight, p)| accum + weight * self.noise(p))
+            .abs()
+    }
+}
+
+/// Shows the seed only, as the tables are too long to be useful.
+impl std::fmt::Debug for Perlin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Perlin").field("seed", &self.seed).finish()
+    }
 }
 
 fn trilinear_interp<'a>(corner_iter: impl Iterator<Item = &'a Vec3>, uvw: Vec3) -> f64 {
@@ -40,24 +101,3 @@ fn trilinear_interp<'a>(corner_iter: impl Iterator<Item = &'a Vec3>, uvw: Vec3)
 fn trilinear_interp_array(corners: &[[[Vec3; 2]; 2]; 2], uvw: Vec3) -> f64 {
     trilinear_interp(corners.iter().flatten().flatten(), uvw)
 }
-
-pub fn noise(p: Vec3) -> f64 {
-    let ijk = p.map(f64::floor);
-    let uvw = p - ijk;
-    let mut corners = [[[Vec3::default(); 2]; 2]; 2];
-    for (idx, corner) in corners.iter_mut().flatten().flatten().enumerate() {
-        let (di, dj, dk) = (idx & 1, (idx & 2) / 2, (idx & 4) / 4);
-        let ix = PERM_X[((ijk.0 as i32 + di as i32) & 255) as usize];
-        let iy = PERM_Y[((ijk.1 as i32 + dj as i32) & 255) as usize];
-        let iz = PERM_Z[((ijk.2 as i32 + dk as i32) & 255) as usize];
-        *corner = VECS[(ix ^ iy ^ iz) as usize];
-    }
-    trilinear_interp_array(&corners, uvw)
-}
-
-pub fn turb(p: Vec3, depth: usize) -> f64 {
-    (1..depth + 1)
-        .map(|i| (1. / i as f64, p * i as f64))
-        .fold(0., |accum, (weight, p)| accum + weight * noise(p))
-        .abs()
-}
@@ -6,6 +6,10 @@ pub struct Ray {
     pub origin: Vec3,
     pub direction: Vec3,
     pub time: f64,
+    /// Wavelength in nanometers that the path this ray belongs to has
+    /// committed to, or `None` while it still carries all colors; see
+    /// `spectrum`.
+    pub wavelength: Option<f64>,
 }
 
 impl Ray {
@@ -21,6 +25,7 @@ impl Ray {
             origin,
             direction,
             time,
+            wavelength: None,
         }
     }
 }
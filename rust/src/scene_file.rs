@@ -0,0 +1,1244 @@
+//! Scenes described in [RON][ron] files rather than Rust code.
+//!
+//! A scene file holds a [`SceneFile`], whose fields mirror the types that
+//! make up a scene, with `Vec3`s written as `(x, y, z)` tuples:
+//!
+//! ```ron
+//! (
+//!     camera: (look_from: (278, 278, -800), look_at: (278, 278, 0), fov: 40),
+//!     materials: {
+//!         "white": Lambertian(albedo: Constant((0.73, 0.73, 0.73))),
+//!     },
+//!     objects: [
+//!         Translate(
+//!             offset: (265, 0, 295),
+//!             object: RotateY(
+//!                 degrees: 15,
+//!                 object: Prism(min: (0, 0, 0), max: (165, 330, 165), material: Named("white")),
+//!             ),
+//!         ),
+//!     ],
+//! )
+//! ```
+//!
+//!
+//! Going the other way, `SceneFile::describe` and `save_scene` write a scene
+//! built in code out to a file.
+//!
+//! [ron]: https://github.com/ron-rs/ron
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bvh::Bvh,
+    camera::Camera,
+    environment::{Environment, EnvironmentLight},
+    loaders::{self, LoadError, Position},
+    objects::{
+        rect_prism, rotate_y, ConstantMedium, FlipNormals, LinearMove, Mesh, MeshData, Object,
+        PdfObject, Rect, Scale, Sphere, StaticX, StaticY, StaticZ, Translate, Triangle,
+    },
+    scenes::Scene,
+    spectrum::{Dispersion, Spd},
+    texture::{self, Texture},
+    vec3::Vec3,
+    ComplexIor, Material,
+};
+
+/// Loads the scene file at `path`, for rendering with the given `aspect`
+/// ratio.
+///
+/// Paths of images and meshes in the file are relative to the file itself.
+pub fn load_scene<P: AsRef<Path>>(path: P, aspect: f64) -> Result<Scene, LoadError> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    let file = SceneFile::parse(&text)?;
+    file.build_spanned(
+        path.parent().unwrap_or_else(|| Path::new("")),
+        aspect,
+        &Spans::find(&text),
+    )
+}
+
+/// Writes `scene` to a scene file at `path`.
+///
+/// Materials used more than once are named, and relative image paths, taken
+/// to be relative to the working directory, are rewritten to be relative to
+/// the file.
+pub fn save_scene<P: AsRef<Path>>(
+    scene: &Scene,
+    path: P,
+    // TODO: Remove boxed error
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let mut file = SceneFile::describe(scene)
+        .ok_or("scene has objects or textures that can't be described")?;
+
+    let dir = match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+    for image in file.images_mut() {
+        *image = relative_to(image, dir)?;
+    }
+    std::fs::write(path, file.to_ron())?;
+    Ok(())
+}
+
+/// Rewrites `path`, relative to the working directory, to be relative to
+/// `dir` instead.
+fn relative_to(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+    let path = std::env::current_dir()?.join(path);
+    let path = path.canonicalize().unwrap_or(path);
+    let dir = dir.canonicalize()?;
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    Ok(dir
+        .components()
+        .skip(common)
+        .map(|_| Component::ParentDir)
+        .chain(path.components().skip(common))
+        .collect())
+}
+
+/// The contents of a scene file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneFile {
+    pub camera: CameraDesc,
+    /// Time span the camera shutter is open for.
+    #[serde(default = "default_exposure")]
+    pub exposure: (f64, f64),
+    #[serde(default)]
+    pub environment: EnvironmentDesc,
+    /// Materials that objects can refer to with `MaterialDesc::Named`.
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDesc>,
+    pub objects: Vec<ObjectDesc>,
+}
+
+fn default_exposure() -> (f64, f64) {
+    (0., 1.)
+}
+
+/// Arguments of `Camera::look`, less the aspect ratio and exposure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraDesc {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    #[serde(default = "default_up")]
+    pub up: Vec3,
+    /// Vertical field of view in degrees.
+    pub fov: f64,
+    #[serde(default)]
+    pub aperture: f64,
+    /// Distance to the plane in focus, by default the one containing
+    /// `look_at`.
+    #[serde(default)]
+    pub focus_dist: Option<f64>,
+}
+
+fn default_up() -> Vec3 {
+    Vec3(0., 1., 0.)
+}
+
+impl CameraDesc {
+    /// Builds the described camera for an image of the given aspect ratio.
+    pub fn build(&self, aspect: f64, exposure: Range<f64>) -> Camera {
+        Camera::look(
+            self.look_from,
+            self.look_at,
+            self.up,
+            self.fov,
+            aspect,
+            self.aperture,
+            self.focus_dist
+                .unwrap_or_else(|| (self.look_at - self.look_from).length()),
+            exposure,
+        )
+    }
+}
+
+/// Describes an `Environment`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EnvironmentDesc {
+    Constant(Vec3),
+    Gradient {
+        bottom: Vec3,
+        top: Vec3,
+    },
+    Map {
+        texture: TextureDesc,
+        brightness: f64,
+        #[serde(default)]
+        rotation: f64,
+    },
+}
+
+impl Default for EnvironmentDesc {
+    fn default() -> Self {
+        EnvironmentDesc::Constant(Vec3::default())
+    }
+}
+
+/// Describes a `Texture`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TextureDesc {
+    /// `texture::constant`.
+    Constant(Vec3),
+    /// `texture::checker`, showing `odd` where the product of the sines of
+    /// the scaled coordinates is negative.
+    Checker {
+        even: Box<TextureDesc>,
+        odd: Box<TextureDesc>,
+        scale: f64,
+    },
+    /// `texture::perlin`, with noise tables generated from `seed` if given
+    /// and shared otherwise.
+    Perlin {
+        scale: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    /// `texture::marble_texture`, with noise like `Perlin`.
+    Marble {
+        scale: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    /// `texture::matte`, with noise like `Perlin`.
+    Matte {
+        scale: f64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seed: Option<u64>,
+    },
+    /// `texture::image_texture`.
+    Image { path: PathBuf },
+    /// Another texture multiplied by `factor`.
+    Scaled {
+        factor: Vec3,
+        texture: Box<TextureDesc>,
+    },
+}
+
+/// Describes a `Material`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MaterialDesc {
+    Lambertian {
+        albedo: TextureDesc,
+    },
+    Metal {
+        albedo: Vec3,
+        fuzz: f64,
+    },
+    /// `Material::Conductor`, with the parts of its complex refractive index.
+    Conductor {
+        eta: Vec3,
+        k: Vec3,
+        roughness: f64,
+    },
+    Dielectric {
+        ref_idx: f64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        absorption: Vec3,
+    },
+    RoughDielectric {
+        ref_idx: f64,
+        roughness: f64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        absorption: Vec3,
+    },
+    Dispersive {
+        dispersion: Dispersion,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        absorption: Vec3,
+    },
+    DiffuseLight {
+        emission: TextureDesc,
+        brightness: f64,
+    },
+    Isotropic {
+        albedo: TextureDesc,
+    },
+    SpectralLight {
+        spd: SpdDesc,
+        brightness: f64,
+    },
+    /// One of `SceneFile::materials`.
+    Named(String),
+}
+
+fn is_zero(v: &Vec3) -> bool {
+    *v == Vec3::default()
+}
+
+/// Describes an `Spd`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SpdDesc {
+    /// `Spd::blackbody`.
+    Blackbody { temperature: f64 },
+    /// `Spd::sampled`.
+    Sampled(Vec<(f64, f64)>),
+}
+
+/// The axis a `Rect` is orthogonal to.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AxisDesc {
+    X,
+    Y,
+    Z,
+}
+
+/// Describes an object and, for transforms, the object it wraps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ObjectDesc {
+    Sphere {
+        center: Vec3,
+        radius: f64,
+        material: MaterialDesc,
+    },
+    Rect {
+        axis: AxisDesc,
+        range0: (f64, f64),
+        range1: (f64, f64),
+        k: f64,
+        material: MaterialDesc,
+    },
+    /// `rect_prism` between two opposite corners.
+    Prism {
+        min: Vec3,
+        max: Vec3,
+        material: MaterialDesc,
+    },
+    Triangle {
+        vertices: [Vec3; 3],
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        normals: Option<[Vec3; 3]>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        uvs: Option<[(f64, f64); 3]>,
+        material: MaterialDesc,
+    },
+    /// An indexed triangle mesh given inline, with the fields of `MeshData`.
+    Mesh {
+        positions: Vec<Vec3>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        normals: Vec<Vec3>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+        material: MaterialDesc,
+    },
+    /// A mesh loaded from an OBJ, PLY or STL file. OBJ files bring their own
+    /// materials, the others get `material`, or a gray one if it is missing.
+    MeshFile {
+        path: PathBuf,
+        #[serde(default)]
+        material: Option<MaterialDesc>,
+    },
+    /// A `ConstantMedium` inside `boundary`.
+    Medium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        material: MaterialDesc,
+    },
+    Translate {
+        offset: Vec3,
+        object: Box<ObjectDesc>,
+    },
+    /// `rotate_y`.
+    RotateY {
+        degrees: f64,
+        object: Box<ObjectDesc>,
+    },
+    Scale {
+        factor: Vec3,
+        object: Box<ObjectDesc>,
+    },
+    FlipNormals(Box<ObjectDesc>),
+    LinearMove {
+        motion: Vec3,
+        object: Box<ObjectDesc>,
+    },
+    /// Objects kept in a BVH of their own, so they can be transformed
+    /// together.
+    Group(Vec<ObjectDesc>),
+}
+
+impl SceneFile {
+    /// Parses the text of a scene file.
+    pub fn parse(text: &str) -> Result<Self, LoadError> {
+        ron::from_str(text).map_err(|e| {
+            LoadError::parse(
+                Position::LineColumn {
+                    line: e.position.line,
+                    column: e.position.col,
+                },
+                e.code.to_string(),
+            )
+        })
+    }
+
+    /// Describes a built scene, or returns `None` if any part of it can't be
+    /// described. Materials used by more than one object are moved into
+    /// `materials`.
+    pub fn describe(scene: &Scene) -> Option<Self> {
+        let mut file = SceneFile {
+            camera: scene.camera.describe(),
+            exposure: (scene.exposure.start, scene.exposure.end),
+            environment: scene.environment.describe()?,
+            materials: BTreeMap::new(),
+            objects: scene
+                .objects
+                .iter()
+                .map(|object| object.describe())
+                .collect::<Option<_>>()?,
+        };
+        file.name_shared_materials();
+        Some(file)
+    }
+
+    /// The scene file as pretty-printed RON.
+    pub fn to_ron(&self) -> String {
+        let config = ron::ser::PrettyConfig::new()
+            .indentor("    ".to_string())
+            .depth_limit(3);
+        ron::ser::to_string_pretty(self, config).expect("scene descriptions always serialize")
+    }
+
+    /// Replaces materials used more than once with references to named ones.
+    fn name_shared_materials(&mut self) {
+        let mut uses = vec![];
+        for object in &mut self.objects {
+            object.materials_mut(&mut uses);
+        }
+
+        let mut counts: Vec<(MaterialDesc, usize)> = vec![];
+        for material in &uses {
+            match counts.iter_mut().find(|(m, _)| m == &**material) {
+                Some((_, count)) => *count += 1,
+                None => counts.push(((**material).clone(), 1)),
+            }
+        }
+        let shared: Vec<MaterialDesc> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(material, _)| material)
+            .collect();
+
+        for material in uses {
+            if let Some(i) = shared.iter().position(|m| m == &*material) {
+                *material = MaterialDesc::Named(format!("material{}", i));
+            }
+        }
+        self.materials.extend(
+            shared
+                .into_iter()
+                .enumerate()
+                .map(|(i, material)| (format!("material{}", i), material)),
+        );
+    }
+
+    /// Paths of all image textures in the file.
+    fn images_mut(&mut self) -> Vec<&mut PathBuf> {
+        let mut materials = vec![];
+        for object in &mut self.objects {
+            object.materials_mut(&mut materials);
+        }
+        materials.extend(self.materials.values_mut());
+
+        let mut textures = vec![];
+        if let EnvironmentDesc::Map { texture, .. } = &mut self.environment {
+            textures.push(texture);
+        }
+        for material in materials {
+            match material {
+                MaterialDesc::Lambertian { albedo } | MaterialDesc::Isotropic { albedo } => {
+                    textures.push(albedo)
+                }
+                MaterialDesc::DiffuseLight { emission, .. } => textures.push(emission),
+                _ => {}
+            }
+        }
+
+        let mut images = vec![];
+        for texture in textures {
+            texture.images_mut(&mut images);
+        }
+        images
+    }
+
+    /// Builds the scene, resolving relative paths against `dir`.
+    ///
+    /// Emissive rects, triangles and inline meshes at the top level of
+    /// `objects` also become the scene's `lights`, as does an environment map.
+    /// Lights inside transforms or groups aren't sampled directly.
+    ///
+    /// Errors don't say where in the file the problem is, as the text of the
+    /// file isn't known here; `load_scene` gives their positions.
+    pub fn build(&self, dir: &Path, aspect: f64) -> Result<Scene, LoadError> {
+        self.build_spanned(dir, aspect, &Spans::default())
+    }
+
+    /// Builds the scene, placing errors in the file with `spans`.
+    fn build_spanned(&self, dir: &Path, aspect: f64, spans: &Spans) -> Result<Scene, LoadError> {
+        let exposure = self.exposure.0..self.exposure.1;
+        let mut builder = Builder {
+            dir,
+            exposure: exposure.clone(),
+            materials: HashMap::new(),
+        };
+        for (name, material) in &self.materials {
+            let at = spans.materials.get(name).copied();
+            if let MaterialDesc::Named(_) = material {
+                return Err(LoadError::Invalid(format!(
+                    "material {:?} is only another name",
+                    name
+                ))
+                .at(at));
+            }
+            let material = builder.material(material).map_err(|e| e.at(at))?;
+            builder.materials.insert(name.clone(), material);
+        }
+        let object_at = |i: usize| spans.objects.get(i).copied();
+        let objects = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| builder.object(object).map_err(|e| e.at(object_at(i))))
+            .collect::<Result<_, _>>()?;
+        let environment = match &self.environment {
+            EnvironmentDesc::Constant(color) => Environment::Constant(*color),
+            EnvironmentDesc::Gradient { bottom, top } => Environment::Gradient {
+                bottom: *bottom,
+                top: *top,
+            },
+            EnvironmentDesc::Map {
+                texture,
+                brightness,
+                rotation,
+            } => Environment::Map {
+                texture: builder
+                    .texture(texture)
+                    .map_err(|e| e.at(spans.fields.get("environment").copied()))?,
+                brightness: *brightness,
+                rotation: *rotation,
+            },
+        };
+        let mut lights: Vec<Box<dyn PdfObject>> = self
+            .objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| builder.lights(object).map_err(|e| e.at(object_at(i))))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        if let Some(light) = EnvironmentLight::for_map(&environment) {
+            lights.push(Box::new(light));
+        }
+
+        Ok(Scene {
+            objects,
+            camera: self.camera.build(aspect, exposure.clone()),
+            exposure,
+            environment,
+            lights,
+        })
+    }
+}
+
+impl TextureDesc {
+    fn images_mut<'a>(&'a mut self, out: &mut Vec<&'a mut PathBuf>) {
+        match self {
+            TextureDesc::Image { path } => out.push(path),
+            TextureDesc::Checker { even, odd, .. } => {
+                even.images_mut(out);
+                odd.images_mut(out);
+            }
+            TextureDesc::Scaled { texture, .. } => texture.images_mut(out),
+            _ => {}
+        }
+    }
+}
+
+impl ObjectDesc {
+    /// Collects the materials of this object and the objects it wraps.
+    fn materials_mut<'a>(&'a mut self, out: &mut Vec<&'a mut MaterialDesc>) {
+        match self {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::Rect { material, .. }
+            | ObjectDesc::Prism { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Mesh { material, .. } => out.push(material),
+            ObjectDesc::MeshFile { material, .. } => out.extend(material),
+            ObjectDesc::Medium {
+                boundary, material, ..
+            } => {
+                boundary.materials_mut(out);
+                out.push(material);
+            }
+            ObjectDesc::Translate { object, .. }
+            | ObjectDesc::RotateY { object, .. }
+            | ObjectDesc::Scale { object, .. }
+            | ObjectDesc::FlipNormals(object)
+            | ObjectDesc::LinearMove { object, .. } => object.materials_mut(out),
+            ObjectDesc::Group(objects) => {
+                for object in objects {
+                    object.materials_mut(out);
+                }
+            }
+        }
+    }
+}
+
+/// Context for turning descriptions into scene objects.
+struct Builder<'a> {
+    dir: &'a Path,
+    exposure: Range<f64>,
+    /// The built `SceneFile::materials`.
+    materials: HashMap<String, Material>,
+}
+
+impl Builder<'_> {
+    fn texture(&self, texture: &TextureDesc) -> Result<Texture, LoadError> {
+        Ok(match texture {
+            TextureDesc::Constant(color) => texture::constant(*color),
+            TextureDesc::Checker { even, odd, scale } => {
+                texture::checker(self.texture(even)?, self.texture(odd)?, *scale)
+            }
+            TextureDesc::Perlin { scale, seed } => reseed(texture::perlin(*scale), *seed),
+            TextureDesc::Marble { scale, seed } => reseed(texture::marble_texture(*scale), *seed),
+            TextureDesc::Matte { scale, seed } => reseed(texture::matte(*scale), *seed),
+            TextureDesc::Image { path } => {
+                let path = self.dir.join(path);
+                texture::image_texture(&path)
+                    .map_err(|e| LoadError::Invalid(format!("{}: {}", path.display(), e)))?
+            }
+            TextureDesc::Scaled { factor, texture } => Texture::Scaled {
+                factor: *factor,
+                texture: Box::new(self.texture(texture)?),
+            },
+        })
+    }
+
+    fn material(&self, material: &MaterialDesc) -> Result<Material, LoadError> {
+        Ok(match material {
+            MaterialDesc::Lambertian { albedo } => Material::Lambertian {
+                albedo: self.texture(albedo)?,
+            },
+            MaterialDesc::Metal { albedo, fuzz } => Material::Metal {
+                albedo: *albedo,
+                fuzz: *fuzz,
+            },
+            MaterialDesc::Conductor { eta, k, roughness } => Material::Conductor {
+                ior: ComplexIor { eta: *eta, k: *k },
+                roughness: *roughness,
+            },
+            MaterialDesc::Dielectric {
+                ref_idx,
+                absorption,
+            } => Material::Dielectric {
+                ref_idx: *ref_idx,
+                absorption: *absorption,
+            },
+            MaterialDesc::RoughDielectric {
+                ref_idx,
+                roughness,
+                absorption,
+            } => Material::RoughDielectric {
+                ref_idx: *ref_idx,
+                roughness: *roughness,
+                absorption: *absorption,
+            },
+            MaterialDesc::Dispersive {
+                dispersion,
+                roughness,
+                absorption,
+            } => Material::Dispersive {
+                dispersion: *dispersion,
+                roughness: *roughness,
+                absorption: *absorption,
+            },
+            MaterialDesc::DiffuseLight {
+                emission,
+                brightness,
+            } => Material::DiffuseLight {
+                emission: self.texture(emission)?,
+                brightness: *brightness,
+            },
+            MaterialDesc::Isotropic { albedo } => Material::Isotropic {
+                albedo: self.texture(albedo)?,
+            },
+            MaterialDesc::SpectralLight { spd, brightness } => Material::SpectralLight {
+                spd: self.spd(spd)?,
+                brightness: *brightness,
+            },
+            MaterialDesc::Named(name) => self
+                .materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| LoadError::Invalid(format!("unknown material {:?}", name)))?,
+        })
+    }
+
+    fn spd(&self, spd: &SpdDesc) -> Result<Spd, LoadError> {
+        match spd {
+            SpdDesc::Blackbody { temperature } if temperature.is_finite() && *temperature > 0. => {
+                Ok(Spd::blackbody(*temperature))
+            }
+            SpdDesc::Blackbody { temperature } => Err(LoadError::Invalid(format!(
+                "blackbody temperature {} isn't a positive number of Kelvin",
+                temperature
+            ))),
+            SpdDesc::Sampled(samples)
+                if samples.iter().all(|&(l, v)| l.is_finite() && v.is_finite()) =>
+            {
+                Ok(Spd::sampled(samples.clone()))
+            }
+            SpdDesc::Sampled(_) => Err(LoadError::Invalid(
+                "sampled spectrum has a wavelength or power that isn't finite".to_string(),
+            )),
+        }
+    }
+
+    fn object(&self, object: &ObjectDesc) -> Result<Box<dyn Object>, LoadError> {
+        Ok(match object {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+            } => Box::new(Sphere {
+                center: *center,
+                radius: *radius,
+                material: self.material(material)?,
+            }),
+            ObjectDesc::Rect {
+                axis,
+                range0,
+                range1,
+                k,
+                material,
+            } => {
+                let (range0, range1, k) = (range0.0..range0.1, range1.0..range1.1, *k);
+                let material = self.material(material)?;
+                match axis {
+                    AxisDesc::X => Box::new(Rect {
+                        orthogonal_to: StaticX,
+                        range0,
+                        range1,
+                        k,
+                        material,
+                    }),
+                    AxisDesc::Y => Box::new(Rect {
+                        orthogonal_to: StaticY,
+                        range0,
+                        range1,
+                        k,
+                        material,
+                    }),
+                    AxisDesc::Z => Box::new(Rect {
+                        orthogonal_to: StaticZ,
+                        range0,
+                        range1,
+                        k,
+                        material,
+                    }),
+                }
+            }
+            ObjectDesc::Prism { min, max, material } => {
+                Box::new(rect_prism(*min, *max, self.material(material)?))
+            }
+            ObjectDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => Box::new(Triangle {
+                vertices: *vertices,
+                normals: *normals,
+                uvs: *uvs,
+                material: self.material(material)?,
+            }),
+            ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+                material,
+            } => Box::new(Mesh::new(
+                MeshData {
+                    positions: positions.clone(),
+                    normals: normals.clone(),
+                    uvs: uvs.clone(),
+                    indices: indices.clone(),
+                },
+                self.material(material)?,
+            )?),
+            ObjectDesc::MeshFile { path, material } => {
+                let path = self.dir.join(path);
+                let material = match material {
+                    Some(material) => self.material(material)?,
+                    None => Material::Lambertian {
+                        albedo: texture::constant(Vec3::from(0.8)),
+                    },
+                };
+                let extension = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(str::to_ascii_lowercase);
+                let loaded = match extension.as_deref() {
+                    Some("obj") => loaders::load_obj(&path).and_then(|objects| self.group(objects)),
+                    Some("ply") => loaders::load_ply(&path, material)
+                        .map(|mesh| Box::new(mesh) as Box<dyn Object>),
+                    Some("stl") => loaders::load_stl(&path, material)
+                        .map(|mesh| Box::new(mesh) as Box<dyn Object>),
+                    _ => Err(LoadError::Invalid("unknown mesh format".to_string())),
+                };
+                loaded.map_err(|e| e.in_file(&path))?
+            }
+            ObjectDesc::Medium {
+                boundary,
+                density,
+                material,
+            } => Box::new(ConstantMedium {
+                boundary: self.object(boundary)?,
+                density: *density,
+                material: self.material(material)?,
+            }),
+            ObjectDesc::Translate { offset, object } => Box::new(Translate {
+                offset: *offset,
+                object: self.object(object)?,
+            }),
+            ObjectDesc::RotateY { degrees, object } => {
+                Box::new(rotate_y(*degrees, self.object(object)?))
+            }
+            ObjectDesc::Scale { factor, object } => Box::new(Scale {
+                factor: *factor,
+                object: self.object(object)?,
+            }),
+            ObjectDesc::FlipNormals(object) => Box::new(FlipNormals(self.object(object)?)),
+            ObjectDesc::LinearMove { motion, object } => Box::new(LinearMove {
+                motion: *motion,
+                object: self.object(object)?,
+            }),
+            ObjectDesc::Group(objects) => self.group(
+                objects
+                    .iter()
+                    .map(|object| self.object(object))
+                    .collect::<Result<_, _>>()?,
+            )?,
+        })
+    }
+
+    /// The parts of `object` that emit light and can be sampled directly.
+    fn lights(&self, object: &ObjectDesc) -> Result<Vec<Box<dyn PdfObject>>, LoadError> {
+        Ok(match object {
+            ObjectDesc::Rect {
+                axis,
+                range0,
+                range1,
+                k,
+                material,
+            } => {
+                let material = self.material(material)?;
+                if !material.is_emitter() {
+                    return Ok(vec![]);
+                }
+                let (range0, range1, k) = (range0.0..range0.1, range1.0..range1.1, *k);
+                vec![match axis {
+                    AxisDesc::X => Box::new(Rect {
+                        orthogonal_to: StaticX,
+                        range0,
+                        range1,
+                        k,
+                        material,
+                    }),
+                    AxisDesc::Y => Box::new(Rect {
+                        orthogonal_to: StaticY,
+                        range0,
+                        range1,
+                        k,
+                        material,
+                    }),
+                    AxisDesc::Z => Box::new(Rect {
+                        orthogonal_to: StaticZ,
+                        range0,
+                        range1,
+                        k,
+                        material,
+                    }),
+                }]
+            }
+            ObjectDesc::Triangle { material, .. } | ObjectDesc::Mesh { material, .. }
+                if !self.material(material)?.is_emitter() =>
+            {
+                vec![]
+            }
+            ObjectDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => vec![Box::new(Triangle {
+                vertices: *vertices,
+                normals: *normals,
+                uvs: *uvs,
+                material: self.material(material)?,
+            })],
+            ObjectDesc::Mesh {
+                positions,
+                normals,
+                uvs,
+                indices,
+                material,
+            } => {
+                // Already checked by `object`.
+                let data = MeshData {
+                    positions: positions.clone(),
+                    normals: normals.clone(),
+                    uvs: uvs.clone(),
+                    indices: indices.clone(),
+                };
+                let material = self.material(material)?;
+                data.triangles(&material)
+                    .map(|triangle| Box::new(triangle) as Box<dyn PdfObject>)
+                    .collect()
+            }
+            // Sampling doesn't depend on the way the surface faces.
+            ObjectDesc::FlipNormals(object) => self.lights(object)?,
+            _ => vec![],
+        })
+    }
+
+    fn group(&self, objects: Vec<Box<dyn Object>>) -> Result<Box<dyn Object>, LoadError> {
+        if objects.is_empty() {
+            return Err(LoadError::Invalid("group has no objects".to_string()));
+        }
+        Ok(Box::new(Bvh::new(objects, self.exposure.clone())))
+    }
+}
+
+/// Where the parts of a scene file start in its text, so that problems found
+/// while building the scene can point at the part at fault.
+#[derive(Debug, Default)]
+struct Spans {
+    /// Top-level fields of the `SceneFile`, by name.
+    fields: HashMap<String, Position>,
+    /// Each of the top-level `objects`.
+    objects: Vec<Position>,
+    /// Each of the `materials`, by name.
+    materials: HashMap<String, Position>,
+}
+
+impl Spans {
+    /// Scans the text of a scene file that has been parsed successfully.
+    fn find(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut positions = Vec::with_capacity(chars.len());
+        let (mut line, mut column) = (1, 1);
+        for &c in &chars {
+            positions.push(Position::LineColumn { line, column });
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        // Index just past the string literal starting at `i`, plain or raw.
+        let string_end = |i: usize| {
+            let raw = chars[i] == 'r';
+            let hashes = if raw {
+                chars[i + 1..].iter().take_while(|&&c| c == '#').count()
+            } else {
+                0
+            };
+            let closes =
+                |j: usize| chars[j + 1..].iter().take_while(|&&c| c == '#').count() >= hashes;
+            let mut j = i + 1 + hashes + usize::from(raw);
+            while j < chars.len() {
+                match chars[j] {
+                    '\\' if !raw => j += 1,
+                    '"' if closes(j) => return j + 1 + hashes,
+                    _ => {}
+                }
+                j += 1;
+            }
+            j
+        };
+
+        let mut spans = Spans::default();
+        // The top-level struct is at depth 1, and the items of `objects` and
+        // `materials` at depth 2.
+        let mut depth = 0;
+        let mut field = String::new();
+        let mut item_next = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            if c.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            if c == '/' && next == Some('/') {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            if c == '/' && next == Some('*') {
+                i += 2;
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                    i += 1;
+                }
+                i += 2;
+                continue;
+            }
+
+            let item = item_next && depth == 2 && !matches!(c, ')' | ']' | '}');
+            item_next = false;
+            if item && field == "objects" {
+                spans.objects.push(positions[i]);
+            }
+
+            let raw = c == 'r' && matches!(next, Some('"') | Some('#'));
+            if c == '"' || raw {
+                let end = string_end(i);
+                if item && field == "materials" {
+                    let literal: String = chars[i..end].iter().collect();
+                    if let Ok(name) = ron::from_str::<String>(&literal) {
+                        spans.materials.insert(name, positions[i]);
+                    }
+                }
+                i = end;
+                continue;
+            }
+            if c.is_alphanumeric() || c == '_' {
+                let end = i + chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_')
+                    .count();
+                if depth == 1 && field.is_empty() {
+                    field = chars[i..end].iter().collect();
+                    spans.fields.insert(field.clone(), positions[i]);
+                }
+                i = end;
+                continue;
+            }
+
+            match c {
+                '(' | '[' | '{' => {
+                    depth += 1;
+                    item_next = depth == 2 && (field == "objects" || field == "materials");
+                }
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 1 => field.clear(),
+                ',' if depth == 2 => item_next = true,
+                _ => {}
+            }
+            i += 1;
+        }
+        spans
+    }
+}
+
+/// Gives `texture` its own noise tables if the file sets a seed for them.
+fn reseed(texture: Texture, seed: Option<u64>) -> Texture {
+    match seed {
+        Some(seed) => texture.with_noise_seed(seed),
+        None => texture,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenes;
+
+    const SCENE: &str = r#"// Two spheres, one lit.
+(
+    camera: (look_from: (0, 0, -5), look_at: (0, 0, 0), fov: 40),
+    materials: {
+        "gray": Lambertian(albedo: Constant((0.5, 0.5, 0.5))),
+    },
+    objects: [
+        Sphere(center: (0, 0, 0), radius: 1, material: Named("gray")),
+        /* The light. */ Rect(axis: Y, range0: (-1, 1), range1: (-1, 1), k: 3,
+            material: DiffuseLight(emission: Constant((1, 1, 1)), brightness: 4)),
+    ],
+)
+"#;
+
+    /// Builds `text` the way `load_scene` does, with error positions.
+    fn build(text: &str) -> Result<Scene, LoadError> {
+        SceneFile::parse(text)?.build_spanned(Path::new(""), 1., &Spans::find(text))
+    }
+
+    fn error_position(text: &str) -> (usize, usize) {
+        match build(text) {
+            Err(LoadError::Parse {
+                position: Position::LineColumn { line, column },
+                ..
+            }) => (line, column),
+            Err(e) => panic!("expected an error with a position, got {}", e),
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn parse_and_build() {
+        let scene = build(SCENE).unwrap();
+        assert_eq!(scene.objects.len(), 2);
+        // The rect at the top level is sampled as a light.
+        assert_eq!(scene.lights.len(), 1);
+        assert_eq!(scene.exposure, 0. ..1.);
+    }
+
+    #[test]
+    fn syntax_error_position() {
+        assert_eq!(
+            error_position("(\n    camera: oops,\n    objects: [],\n)"),
+            (2, 17)
+        );
+    }
+
+    #[test]
+    fn build_error_positions() {
+        // Unknown material in the second object.
+        let text = SCENE.replace("Named(\"gray\")", "Named(\"grey\")");
+        assert_eq!(error_position(&text), (8, 9));
+        // Bad material definition.
+        let text = SCENE.replace(
+            "Lambertian(albedo: Constant((0.5, 0.5, 0.5)))",
+            "Named(\"gray\")",
+        );
+        assert_eq!(error_position(&text), (5, 9));
+        // Empty group, after a comment.
+        let text = SCENE.replace("/* The light. */ Rect", "/* The light. */ Group([]), Rect");
+        assert_eq!(error_position(&text), (9, 26));
+        // Mesh files.
+        for path in &["mesh.xyz", "missing.obj"] {
+            let text = SCENE.replace(
+                "Sphere(center: (0, 0, 0), radius: 1, material: Named(\"gray\"))",
+                &format!("MeshFile(path: {:?})", path),
+            );
+            assert_eq!(error_position(&text), (8, 9), "{}", path);
+        }
+        // Emissive mesh with an index out of range, which must be caught
+        // before it is sampled as a light.
+        let text = SCENE.replace(
+            "Sphere(center: (0, 0, 0), radius: 1, material: Named(\"gray\"))",
+            "Mesh(positions: [(0, 0, 0)], indices: [(0, 1, 2)], material: DiffuseLight(\
+             emission: Constant((1, 1, 1)), brightness: 1))",
+        );
+        assert_eq!(error_position(&text), (8, 9));
+        // Environment textures.
+        let text = SCENE.replace(
+            "    materials",
+            "    environment: Map(texture: Image(path: \"missing.hdr\"), brightness: 1),\n    materials",
+        );
+        assert_eq!(error_position(&text), (4, 5));
+    }
+
+    #[test]
+    fn invalid_spectra() {
+        let light = "DiffuseLight(emission: Constant((1, 1, 1)), brightness: 4)";
+        for spd in &[
+            "Blackbody(temperature: 0)",
+            "Blackbody(temperature: -6500)",
+            "Blackbody(temperature: inf)",
+            "Blackbody(temperature: NaN)",
+            "Sampled([(400, 1), (700, inf)])",
+            "Sampled([(NaN, 1)])",
+        ] {
+            let text = SCENE.replace(
+                light,
+                &format!("SpectralLight(spd: {}, brightness: 4)", spd),
+            );
+            assert_eq!(error_position(&text), (9, 26), "{}", spd);
+        }
+        let text = SCENE.replace(
+            light,
+            "SpectralLight(spd: Blackbody(temperature: 6500), brightness: 4)",
+        );
+        assert_eq!(build(&text).unwrap().lights.len(), 1);
+    }
+
+    #[test]
+    fn spans_skip_strings_and_comments() {
+        let spans = Spans::find(
+            "( // objects: [\n  materials: { \"a, \\\"b\\\"\": X, r#\"c\"# : Y },\n  objects: [A, /* , */ B([C, D])],\n)",
+        );
+        assert_eq!(
+            spans.materials["a, \"b\""],
+            Position::LineColumn {
+                line: 2,
+                column: 16
+            }
+        );
+        assert_eq!(
+            spans.materials["c"],
+            Position::LineColumn {
+                line: 2,
+                column: 31
+            }
+        );
+        assert_eq!(
+            spans.objects,
+            [
+                Position::LineColumn {
+                    line: 3,
+                    column: 13
+                },
+                Position::LineColumn {
+                    line: 3,
+                    column: 24
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn describe_round_trip() {
+        for name in &["cornell_box", "motion_test", "volume_test", "simple_light"] {
+            let scene = scenes::find(name).unwrap().build(4, 4, 1);
+            let ron = SceneFile::describe(&scene).unwrap().to_ron();
+            let rebuilt = SceneFile::parse(&ron)
+                .unwrap()
+                .build(Path::new(""), 1.)
+                .unwrap();
+            assert_eq!(rebuilt.objects.len(), scene.objects.len(), "{}", name);
+            assert_eq!(
+                SceneFile::describe(&rebuilt).unwrap().to_ron(),
+                ron,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn bundled_scenes() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for name in &["cornell_box.ron", "volume_test.ron"] {
+            let scene = load_scene(dir.join(name), 1.).unwrap();
+            assert!(!scene.lights.is_empty(), "{}", name);
+        }
+        // Its image isn't bundled.
+        SceneFile::parse(&std::fs::read_to_string(dir.join("book_final.ron")).unwrap()).unwrap();
+    }
+}
@@ -1,55 +1,154 @@
 use crate::{
     bvh,
     camera::Camera,
+    environment::{Environment, EnvironmentLight},
     material::{self, Material},
     objects::*,
+    scene_file::CameraDesc,
     texture,
     vec3::Vec3,
 };
-use crate::{cornell_box, cornell_box_with_boxes};
-use rand::prelude::*;
+use crate::{cornell_box, cornell_box_lights, cornell_box_with_boxes};
+use rand::{prelude::*, rngs::SmallRng};
 use std::ops::Range;
 
-#[allow(unused)]
-pub fn cornell_box_scene(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Camera, Range<f64>) {
-    let look_from = Vec3(278., 278., -800.);
-    let look_at = Vec3(278., 278., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.0;
-    let exposure = 0. ..1.;
+/// Everything needed to render one of the scenes below.
+pub struct Scene {
+    /// The objects making up the scene.
+    pub objects: Vec<Box<dyn Object>>,
+    /// The camera looking at the scene.
+    pub camera: Camera,
+    /// Time span the camera shutter is open for.
+    pub exposure: Range<f64>,
+    /// Light reaching the scene from beyond its objects.
+    pub environment: Environment,
+    /// Emitters among the objects and the environment map, if any, for
+    /// integrators that sample lights directly. Leaving it empty only costs
+    /// noise.
+    pub lights: Vec<Box<dyn PdfObject>>,
+}
+
+/// Builds a scene for a `nx` x `ny` image, drawing any random placement from
+/// the given generator.
+pub type SceneFn = fn(usize, usize, &mut SmallRng) -> Scene;
+
+/// A built-in scene, as listed in `SCENES`.
+pub struct SceneInfo {
+    /// Name the scene is looked up by.
+    pub name: &'static str,
+    /// One-line description of what the scene shows.
+    pub description: &'static str,
+    /// Samples per pixel for a render without distracting noise.
+    pub samples: usize,
+    /// The camera the scene is viewed through.
+    pub camera: CameraDesc,
+    /// Builds the scene; see also `build`.
+    pub constructor: SceneFn,
+}
+
+impl SceneInfo {
+    /// Builds the scene for a `nx` x `ny` image, with its random placement
+    /// drawn from a generator seeded with `seed`.
+    pub fn build(&self, nx: usize, ny: usize, seed: u64) -> Scene {
+        (self.constructor)(nx, ny, &mut SmallRng::seed_from_u64(seed))
+    }
+}
 
-    let camera = Camera::look(
-        look_from,
-        look_at,
-        Vec3(0., 1., 0.),
-        40.,
-        nx as f64 / ny as f64,
-        aperture,
-        dist_to_focus,
-        exposure.clone(),
-    );
-
-    (cornell_box_with_boxes(), camera, exposure)
+/// Every built-in scene.
+pub static SCENES: &[SceneInfo] = &[
+    SceneInfo {
+        name: "cornell_box",
+        description: "Cornell box with two rotated boxes",
+        samples: 1000,
+        camera: CORNELL_CAMERA,
+        constructor: |nx, ny, _| cornell_box_scene(nx, ny),
+    },
+    SceneInfo {
+        name: "motion_test",
+        description: "Cornell box with a sphere moving during the exposure",
+        samples: 1000,
+        camera: CORNELL_CAMERA,
+        constructor: |nx, ny, _| motion_test(nx, ny),
+    },
+    SceneInfo {
+        name: "volume_test",
+        description: "Cornell box with a sphere of blue fog",
+        samples: 2000,
+        camera: CORNELL_CAMERA,
+        constructor: |nx, ny, _| volume_test(nx, ny),
+    },
+    SceneInfo {
+        name: "simple_light",
+        description: "Cornell box filled with small spheres, under a dim sky",
+        samples: 500,
+        camera: CORNELL_CAMERA,
+        constructor: |nx, ny, rng| simple_light_scene(nx, ny, rng),
+    },
+    SceneInfo {
+        name: "textured_sphere",
+        description: "Glowing image-textured sphere under a matte sky",
+        samples: 200,
+        camera: TEXTURED_SPHERE_CAMERA,
+        constructor: |nx, ny, _| scene_textured_sphere(nx, ny),
+    },
+    SceneInfo {
+        name: "book_final",
+        description: "Final scene of The Next Week",
+        samples: 2000,
+        camera: BOOK_FINAL_CAMERA,
+        constructor: |nx, ny, rng| book_final_scene(nx, ny, rng),
+    },
+];
+
+/// Looks up the built-in scene called `name`.
+pub fn find(name: &str) -> Option<&'static SceneInfo> {
+    SCENES.iter().find(|scene| scene.name == name)
 }
 
-#[allow(unused)]
-pub fn motion_test(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Camera, Range<f64>) {
-    let look_from = Vec3(278., 278., -800.);
-    let look_at = Vec3(278., 278., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.0;
+const CORNELL_CAMERA: CameraDesc = CameraDesc {
+    look_from: Vec3(278., 278., -800.),
+    look_at: Vec3(278., 278., 0.),
+    up: Vec3(0., 1., 0.),
+    fov: 40.,
+    aperture: 0.,
+    focus_dist: Some(10.),
+};
+
+/// Looks at the sphere from 1 degree above the horizon.
+const TEXTURED_SPHERE_CAMERA: CameraDesc = CameraDesc {
+    look_from: Vec3(674.8972, 11.7804, 0.),
+    look_at: Vec3(0., 0., 0.),
+    up: Vec3(0., 1., 0.),
+    fov: 40.,
+    aperture: 0.,
+    focus_dist: Some(10.),
+};
+
+const BOOK_FINAL_CAMERA: CameraDesc = CameraDesc {
+    look_from: Vec3(478., 278., -600.),
+    look_at: Vec3(278., 278., 0.),
+    up: Vec3(0., 1., 0.),
+    fov: 40.,
+    aperture: 0.,
+    focus_dist: Some(10.),
+};
+
+pub fn cornell_box_scene(nx: usize, ny: usize) -> Scene {
     let exposure = 0. ..1.;
+    let camera = CORNELL_CAMERA.build(nx as f64 / ny as f64, exposure.clone());
+
+    Scene {
+        objects: cornell_box_with_boxes(),
+        camera,
+        exposure,
+        environment: Environment::default(),
+        lights: cornell_box_lights(),
+    }
+}
 
-    let camera = Camera::look(
-        look_from,
-        look_at,
-        Vec3(0., 1., 0.),
-        40.,
-        nx as f64 / ny as f64,
-        aperture,
-        dist_to_focus,
-        exposure.clone(),
-    );
+pub fn motion_test(nx: usize, ny: usize) -> Scene {
+    let exposure = 0. ..1.;
+    let camera = CORNELL_CAMERA.build(nx as f64 / ny as f64, exposure.clone());
 
     let mut scene = cornell_box();
 
@@ -64,27 +163,18 @@ pub fn motion_test(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Camera, Range
         },
     }));
 
-    (scene, camera, exposure)
+    Scene {
+        objects: scene,
+        camera,
+        exposure,
+        environment: Environment::default(),
+        lights: cornell_box_lights(),
+    }
 }
 
-#[allow(unused)]
-pub fn volume_test(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Camera, Range<f64>) {
-    let look_from = Vec3(278., 278., -800.);
-    let look_at = Vec3(278., 278., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.0;
+pub fn volume_test(nx: usize, ny: usize) -> Scene {
     let exposure = 0. ..1.;
-
-    let camera = Camera::look(
-        look_from,
-        look_at,
-        Vec3(0., 1., 0.),
-        40.,
-        nx as f64 / ny as f64,
-        aperture,
-        dist_to_focus,
-        exposure.clone(),
-    );
+    let camera = CORNELL_CAMERA.build(nx as f64 / ny as f64, exposure.clone());
 
     let mut scene = cornell_box();
 
@@ -103,31 +193,18 @@ pub fn volume_test(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Camera, Range
         },
     }));
 
-    (scene, camera, exposure)
+    Scene {
+        objects: scene,
+        camera,
+        exposure,
+        environment: Environment::default(),
+        lights: cornell_box_lights(),
+    }
 }
 
-#[allow(unused)]
-pub fn simple_light_scene(
-    nx: usize,
-    ny: usize,
-    rng: &mut impl Rng,
-) -> (Vec<Box<dyn Object>>, Camera, Range<f64>) {
-    let look_from = Vec3(278., 278., -800.);
-    let look_at = Vec3(278., 278., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.0;
+pub fn simple_light_scene(nx: usize, ny: usize, rng: &mut impl Rng) -> Scene {
     let exposure = 0. ..1.;
-
-    let camera = Camera::look(
-        look_from,
-        look_at,
-        Vec3(0., 1., 0.),
-        40.,
-        nx as f64 / ny as f64,
-        aperture,
-        dist_to_focus,
-        exposure.clone(),
-    );
+    let camera = CORNELL_CAMERA.build(nx as f64 / ny as f64, exposure.clone());
 
     use material::Material;
 
@@ -154,34 +231,30 @@ pub fn simple_light_scene(
         },
     })));
 
-    (world, camera, exposure)
+    Scene {
+        objects: world,
+        camera,
+        exposure,
+        environment: Environment::default(),
+        lights: cornell_box_lights(),
+    }
 }
 
-#[allow(unused)]
-pub fn scene_textured_sphere(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Camera, Range<f64>) {
-    let r = 675.;
-    let theta = 90. * std::f64::consts::PI / 180.;
-    let phi = 1. * std::f64::consts::PI / 180.;
-    let x = r * theta.sin() * phi.cos();
-    let y = r * theta.sin() * phi.sin();
-    let z = r * theta.cos();
-
-    let look_from = Vec3(x, y, z);
-    let look_at = Vec3(0., 0., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.0;
+pub fn scene_textured_sphere(nx: usize, ny: usize) -> Scene {
     let exposure = 0. ..1.;
+    let camera = TEXTURED_SPHERE_CAMERA.build(nx as f64 / ny as f64, exposure.clone());
 
-    let camera = Camera::look(
-        look_from,
-        look_at,
-        Vec3(0., 1., 0.),
-        40.,
-        nx as f64 / ny as f64,
-        aperture,
-        dist_to_focus,
-        exposure.clone(),
-    );
+    let light = || Rect {
+        orthogonal_to: StaticX,
+        range0: -123. ..423.,
+        range1: -112. ..412.,
+        k: 950.,
+        material: Material::DiffuseLight {
+            // emission: texture::constant(Vec3(227., 193., 111.).map(|x| x / 255.)),
+            emission: texture::constant(Vec3::from(0.5)),
+            brightness: 20.,
+        },
+    };
 
     #[allow(clippy::vec_init_then_push)]
     let world = {
@@ -191,7 +264,7 @@ pub fn scene_textured_sphere(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Cam
         world.push(Box::new(rotate_y(
             23.,
             Sphere {
-                center: look_at,
+                center: Vec3(0., 0., 0.),
                 radius: 60.,
                 material: Material::DiffuseLight {
                     emission: texture::image_texture("assets/jasmine.png").unwrap(),
@@ -213,59 +286,35 @@ pub fn scene_textured_sphere(nx: usize, ny: usize) -> (Vec<Box<dyn Object>>, Cam
         //     material: ground,
         // }));
         //
-        world.push(Box::new(rotate_y(
-            190.,
-            FlipNormals(Sphere {
-                center: Vec3(0., 0., 0.),
-                radius: 100000.,
-                material: Material::DiffuseLight {
-                    // emission: texture::constant(Vec3(1., 1., 1.)),
-                    // emission: texture::image_texture("assets/earthmap.jpg").unwrap(),
-                    emission: texture::matte(0.000091),
-                    // emission: texture::perlin(0.00002),
-                    brightness: 2.,
-                },
-            }),
-        )));
-
         // Make light.
-        world.push(Box::new(Rect {
-            orthogonal_to: StaticX,
-            range0: -123. ..423.,
-            range1: -112. ..412.,
-            k: 950.,
-            material: Material::DiffuseLight {
-                // emission: texture::constant(Vec3(227., 193., 111.).map(|x| x / 255.)),
-                emission: texture::constant(Vec3::from(0.5)),
-                brightness: 20.,
-            },
-        }));
+        world.push(Box::new(light()));
         world
     };
-    (world, camera, exposure)
+    let environment = Environment::Map {
+        // texture: texture::constant(Vec3(1., 1., 1.)),
+        // texture: texture::image_texture("assets/earthmap.jpg").unwrap(),
+        texture: texture::matte(9.1),
+        // texture: texture::perlin(2.),
+        brightness: 2.,
+        rotation: 190.,
+    };
+    Scene {
+        objects: world,
+        camera,
+        exposure,
+        lights: std::iter::once(Box::new(light()) as Box<dyn PdfObject>)
+            .chain(
+                EnvironmentLight::for_map(&environment)
+                    .map(|light| Box::new(light) as Box<dyn PdfObject>),
+            )
+            .collect(),
+        environment,
+    }
 }
 
-pub fn book_final_scene(
-    nx: usize,
-    ny: usize,
-    rng: &mut impl Rng,
-) -> (Vec<Box<dyn Object>>, Camera, Range<f64>) {
-    let look_from = Vec3(478., 278., -600.);
-    let look_at = Vec3(278., 278., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.0;
+pub fn book_final_scene(nx: usize, ny: usize, rng: &mut impl Rng) -> Scene {
     let exposure = 0. ..1.;
-
-    let camera = Camera::look(
-        look_from,
-        look_at,
-        Vec3(0., 1., 0.),
-        40.,
-        nx as f64 / ny as f64,
-        aperture,
-        dist_to_focus,
-        exposure.clone(),
-    );
+    let camera = BOOK_FINAL_CAMERA.build(nx as f64 / ny as f64, exposure.clone());
 
     let ground = Material::Lambertian {
         albedo: texture::constant(Vec3(0.48, 0.83, 0.53)),
@@ -288,7 +337,7 @@ pub fn book_final_scene(
     });
 
     // Make light.
-    world.push(Box::new(Rect {
+    let light = || Rect {
         orthogonal_to: StaticY,
         range0: 123. ..423.,
         range1: 147. ..412.,
@@ -297,7 +346,8 @@ pub fn book_final_scene(
             emission: texture::constant(Vec3::from(1.)),
             brightness: 7.,
         },
-    }));
+    };
+    world.push(Box::new(light()));
 
     // Brown blurry sphere.
     world.push(Box::new(LinearMove {
@@ -311,7 +361,10 @@ pub fn book_final_scene(
         },
     }));
 
-    let glass = Material::Dielectric { ref_idx: 1.5 };
+    let glass = Material::Dielectric {
+        ref_idx: 1.5,
+        absorption: Vec3::default(),
+    };
 
     // Glass sphere.
     world.push(Box::new(Sphere {
@@ -398,5 +451,11 @@ pub fn book_final_scene(
         }
     }));
 
-    (world, camera, exposure)
+    Scene {
+        objects: world,
+        camera,
+        exposure,
+        environment: Environment::default(),
+        lights: vec![Box::new(light())],
+    }
 }
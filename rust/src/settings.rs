@@ -0,0 +1,64 @@
+use rand::{rngs::SmallRng, SeedableRng};
+
+/// Parameters of a render that don't depend on the scene being rendered.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// Width of the image in pixels.
+    pub width: usize,
+    /// Height of the image in pixels.
+    pub height: usize,
+    /// Number of samples averaged per pixel.
+    pub samples: usize,
+    /// Maximum number of times a path may bounce before it is terminated.
+    pub max_depth: usize,
+    /// Number of bounces after which paths are subject to Russian roulette,
+    /// or `None` to only terminate them at `max_depth`.
+    pub roulette_depth: Option<usize>,
+    /// Distance along a ray before which intersections are ignored, so that
+    /// rays leaving a surface don't hit that same surface due to rounding.
+    pub ray_epsilon: f64,
+    /// Seed for the random number generators.
+    pub seed: u64,
+    /// Number of threads used by `par_cast`, or `None` for one per core.
+    pub threads: Option<usize>,
+}
+
+impl RenderSettings {
+    /// Ratio of the image width to its height.
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+
+    /// Generator for sample `sample` of pixel (`x`, `y`).
+    ///
+    /// Every sample has its own stream derived from `seed`, so a render
+    /// doesn't depend on the order its samples are taken in, or on how many
+    /// threads take them.
+    pub fn sample_rng(&self, x: usize, y: usize, sample: usize) -> SmallRng {
+        let index = ((y * self.width + x) * self.samples + sample) as u64;
+        SmallRng::seed_from_u64(mix(mix(self.seed) ^ index))
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 200,
+            height: 200,
+            samples: 500,
+            max_depth: 50,
+            roulette_depth: None,
+            ray_epsilon: 0.001,
+            seed: 0xDEADBEEF,
+            threads: None,
+        }
+    }
+}
+
+/// The SplitMix64 finalizer, which scrambles nearby inputs into unrelated
+/// outputs.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
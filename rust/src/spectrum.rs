@@ -0,0 +1,252 @@
+//! Single wavelengths of light, for effects like dispersion that RGB colors
+//! can't represent.
+//!
+//! Paths start out carrying RGB colors. At the first surface whose behavior
+//! depends on the wavelength, the path commits to one wavelength drawn with
+//! `sample_wavelength`, which its rays carry from then on. Its color is
+//! upsampled to that wavelength with `rgb_to_spectrum`, and the light it finds
+//! is converted back to color only on reaching the film, weighted by the RGB
+//! response to the wavelength from `wavelength_weight`. Averaged over many
+//! paths, the weights come to white, so surfaces that don't depend on the
+//! wavelength look much the same as in plain RGB rendering.
+//!
+//! The `Spectral` integrator instead traces every path at a single wavelength
+//! from the start, upsampling RGB colors to spectra with `rgb_to_spectrum`
+//! and converting the result to color through the CIE color matching
+//! functions, for checking the color accuracy of RGB rendering.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{scene_file::SpdDesc, vec3::Vec3};
+
+/// Shortest wavelength sampled, in nanometers.
+pub const LAMBDA_MIN: f64 = 380.;
+/// Longest wavelength sampled, in nanometers.
+pub const LAMBDA_MAX: f64 = 780.;
+
+/// The CIE 1931 2° color matching functions at `lambda` nanometers, as the
+/// multi-lobe Gaussian fit of Wyman, Sloan and Shirley, "Simple Analytic
+/// Approximations to the CIE XYZ Color Matching Functions" (2013).
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, below: f64, above: f64| {
+        let t = (lambda - mu) / if lambda < mu { below } else { above };
+        (-0.5 * t * t).exp()
+    };
+    Vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Converts CIE XYZ to linear sRGB, with the D65 white point.
+pub fn xyz_to_rgb(xyz: Vec3) -> Vec3 {
+    Vec3(
+        3.240_454_2 * xyz.0 - 1.537_138_5 * xyz.1 - 0.498_531_4 * xyz.2,
+        -0.969_266_0 * xyz.0 + 1.876_010_8 * xyz.1 + 0.041_556_0 * xyz.2,
+        0.055_643_4 * xyz.0 - 0.204_025_9 * xyz.1 + 1.057_225_2 * xyz.2,
+    )
+}
+
+lazy_static::lazy_static! {
+    /// Average linear sRGB response over the sampled wavelengths.
+    static ref MEAN_RGB: Vec3 = {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        (0..steps)
+            .map(|i| xyz_to_rgb(cie_xyz(LAMBDA_MIN + i as f64 + 0.5)))
+            .sum::<Vec3>()
+            / steps as f64
+    };
+}
+
+/// Draws a wavelength uniformly from the visible range.
+pub fn sample_wavelength(rng: &mut impl Rng) -> f64 {
+    rng.gen_range(LAMBDA_MIN..LAMBDA_MAX)
+}
+
+/// Factor converting the color of a path to that of the single wavelength
+/// `lambda` drawn by `sample_wavelength`. Components may be negative for
+/// colors outside the sRGB gamut.
+pub fn wavelength_weight(lambda: f64) -> Vec3 {
+    xyz_to_rgb(cie_xyz(lambda)) / *MEAN_RGB
+}
+
+/// A refractive index that varies with the wavelength, which splits white
+/// light into its colors.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Dispersion {
+    /// Cauchy's equation `n = a + b / λ²`, with `λ` in micrometers.
+    Cauchy { a: f64, b: f64 },
+    /// The Sellmeier equation `n² = 1 + Σ bᵢ λ² / (λ² - cᵢ)`, with `λ` in
+    /// micrometers and `cᵢ` in square micrometers.
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// Schott N-BK7, a common optical crown glass.
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+        c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+    };
+    /// Schott SF11, a dense flint glass with strong dispersion.
+    pub const SF11: Dispersion = Dispersion::Sellmeier {
+        b: [1.737_596_95, 0.313_747_346, 1.898_781_01],
+        c: [0.013_188_707, 0.062_306_814_2, 155.236_29],
+    };
+    /// Diamond.
+    pub const DIAMOND: Dispersion = Dispersion::Sellmeier {
+        b: [4.3356, 0.3306, 0.],
+        c: [0.1060 * 0.1060, 0.1750 * 0.1750, 0.],
+    };
+
+    /// Refractive index at `lambda` nanometers.
+    pub fn ior(&self, lambda: f64) -> f64 {
+        let um = lambda / 1000.;
+        let um2 = um * um;
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / um2,
+            Dispersion::Sellmeier { b, c } => (1.
+                + b.iter()
+                    .zip(c)
+                    .map(|(b, c)| b * um2 / (um2 - c))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Inverse of the matrix taking the weights of the `basis` functions to
+    /// the color `wavelength_weight` averages them to.
+    static ref BASIS_INVERSE: [Vec3; 3] = {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let mut columns = [Vec3::default(); 3];
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + i as f64 + 0.5;
+            let (b, w) = (basis(lambda), wavelength_weight(lambda));
+            columns[0] = columns[0] + b.0 * w / steps as f64;
+            columns[1] = columns[1] + b.1 * w / steps as f64;
+            columns[2] = columns[2] + b.2 * w / steps as f64;
+        }
+        invert(columns)
+    };
+}
+
+/// Smooth red, green and blue bands of the spectrum, which sum to 1 at every
+/// wavelength.
+fn basis(lambda: f64) -> Vec3 {
+    let step = |edge: f64| 1. / (1. + (-(lambda - edge) / 10.).exp());
+    let (blue_green, green_red) = (step(490.), step(585.));
+    Vec3(green_red, blue_green - green_red, 1. - blue_green)
+}
+
+/// Inverts the 3x3 matrix with the given columns, returning its columns.
+fn invert([a, b, c]: [Vec3; 3]) -> [Vec3; 3] {
+    let det = a.dot(b.cross(&c));
+    // The rows of the inverse are the cross products of pairs of columns.
+    let rows = [b.cross(&c) / det, c.cross(&a) / det, a.cross(&b) / det];
+    [
+        Vec3(rows[0].0, rows[1].0, rows[2].0),
+        Vec3(rows[0].1, rows[1].1, rows[2].1),
+        Vec3(rows[0].2, rows[1].2, rows[2].2),
+    ]
+}
+
+/// Value at `lambda` nanometers of a smooth spectrum with linear sRGB color
+/// `rgb`, as seen through `wavelength_weight`.
+///
+/// The spectrum is a mix of red, green and blue bands, weighted so that
+/// converting it back gives exactly `rgb`. White becomes a flat spectrum of
+/// 1; highly saturated colors may dip slightly below zero in places.
+pub fn rgb_to_spectrum(rgb: Vec3, lambda: f64) -> f64 {
+    let [x, y, z] = *BASIS_INVERSE;
+    let weights = rgb.0 * x + rgb.1 * y + rgb.2 * z;
+    weights.dot(basis(lambda))
+}
+
+/// A spectral power distribution, for light sources whose spectrum isn't well
+/// described by an RGB color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spd {
+    shape: Shape,
+    /// Linear sRGB color of the distribution, for rendering in RGB.
+    rgb: Vec3,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    Blackbody { temperature: f64 },
+    Sampled(Vec<(f64, f64)>),
+}
+
+impl Spd {
+    /// Thermal emission of a black body at `temperature` Kelvin, scaled to 1
+    /// at its peak.
+    pub fn blackbody(temperature: f64) -> Self {
+        Spd::new(Shape::Blackbody { temperature })
+    }
+
+    /// Linear interpolation between `(wavelength, power)` samples, with the
+    /// wavelengths in nanometers, and zero outside of them.
+    pub fn sampled(mut samples: Vec<(f64, f64)>) -> Self {
+        samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Spd::new(Shape::Sampled(samples))
+    }
+
+    fn new(shape: Shape) -> Self {
+        let mut spd = Spd {
+            shape,
+            rgb: Vec3::default(),
+        };
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        spd.rgb = (0..steps)
+            .map(|i| {
+                let lambda = LAMBDA_MIN + i as f64 + 0.5;
+                spd.value(lambda) * wavelength_weight(lambda)
+            })
+            .sum::<Vec3>()
+            / steps as f64;
+        spd
+    }
+
+    /// Power at `lambda` nanometers.
+    pub fn value(&self, lambda: f64) -> f64 {
+        match &self.shape {
+            Shape::Blackbody { temperature } => {
+                // Wien's displacement law gives the peak.
+                let peak = 2.897_771_955e6 / temperature;
+                planck(lambda, *temperature) / planck(peak, *temperature)
+            }
+            Shape::Sampled(samples) => {
+                let i = samples.partition_point(|&(l, _)| l < lambda);
+                match (i.checked_sub(1).map(|i| samples[i]), samples.get(i)) {
+                    (Some((l0, v0)), Some(&(l1, v1))) => v0 + (v1 - v0) * (lambda - l0) / (l1 - l0),
+                    (None, Some(&(l1, v1))) if l1 == lambda => v1,
+                    _ => 0.,
+                }
+            }
+        }
+    }
+
+    /// The linear sRGB color of the distribution.
+    pub fn rgb(&self) -> Vec3 {
+        self.rgb
+    }
+
+    /// Describes the distribution for a scene file.
+    pub fn describe(&self) -> SpdDesc {
+        match &self.shape {
+            Shape::Blackbody { temperature } => SpdDesc::Blackbody {
+                temperature: *temperature,
+            },
+            Shape::Sampled(samples) => SpdDesc::Sampled(samples.clone()),
+        }
+    }
+}
+
+/// Planck's law, up to a constant factor, at `lambda` nanometers.
+fn planck(lambda: f64, temperature: f64) -> f64 {
+    const C2: f64 = 1.438_776_877e7; // nm K
+    1. / (lambda.powi(5) * ((C2 / (lambda * temperature)).exp() - 1.))
+}
@@ -1,48 +1,184 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
+
+pub use crate::perlin::Perlin;
 
 use crate::{
-    image_texture::{load_image, map_image},
+    image_texture::{load_image, map_image, Texels, Wrap},
     perlin,
+    scene_file::TextureDesc,
     vec3::Vec3,
 };
 
-pub type Texture = Arc<dyn Fn(f64, f64, Vec3) -> Vec3 + Send + Sync>;
+/// A color varying over the surface of an object, looked up by the texture
+/// coordinates and position of a hit.
+///
+/// Textures are plain data rather than closures, so that a built scene can be
+/// inspected and written back out to a scene file.
+#[derive(Clone, Debug)]
+pub enum Texture {
+    /// The same color everywhere.
+    Constant(Vec3),
+    /// A solid checker pattern, showing `odd` where the product of the sines
+    /// of the scaled coordinates is negative.
+    Checker {
+        even: Box<Texture>,
+        odd: Box<Texture>,
+        scale: f64,
+    },
+    /// Gray Perlin turbulence.
+    Perlin { scale: f64, noise: Arc<Perlin> },
+    /// Marble-like stripes along Z, perturbed by turbulence.
+    Marble { scale: f64, noise: Arc<Perlin> },
+    /// Soft stripes along X, perturbed by turbulence.
+    Matte { scale: f64, noise: Arc<Perlin> },
+    /// An image mapped by the texture coordinates, with the file it was loaded
+    /// from, if any, and how `u` and `v` wrap outside of `[0, 1]`.
+    Image {
+        path: Option<PathBuf>,
+        texels: Arc<Texels>,
+        wrap: [Wrap; 2],
+    },
+    /// Another texture multiplied by `factor`.
+    Scaled { factor: Vec3, texture: Box<Texture> },
+}
+
+impl Texture {
+    /// The color at texture coordinates `u`, `v` and position `p`.
+    pub fn value(&self, u: f64, v: f64, p: Vec3) -> Vec3 {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Checker { even, odd, scale } => {
+                let s = (*scale * p).map(f64::sin).reduce(std::ops::Mul::mul);
+                if s < 0. {
+                    odd.value(u, v, p)
+                } else {
+                    even.value(u, v, p)
+                }
+            }
+            Texture::Perlin { scale, noise } => Vec3::from(noise.turb(*scale * p, 7)),
+            Texture::Marble { scale, noise } => {
+                (0.5 * (1. + (scale * p.2 + 10. * noise.turb(p, 7)).sin())) * Vec3(1., 1., 1.)
+            }
+            Texture::Matte { scale, noise } => {
+                Vec3::from(0.5 * (1. + f64::sin(scale * p.0 + 5. * noise.turb(*scale * p, 7))))
+            }
+            Texture::Image { texels, wrap, .. } => map_image(u, v, texels, *wrap),
+            Texture::Scaled { factor, texture } => *factor * texture.value(u, v, p),
+        }
+    }
+
+    /// Width and height of the image an image texture maps, or `None` for
+    /// other textures.
+    pub fn resolution(&self) -> Option<(usize, usize)> {
+        match self {
+            Texture::Image { texels, .. } => Some((texels.width, texels.height)),
+            Texture::Scaled { texture, .. } => texture.resolution(),
+            _ => None,
+        }
+    }
+
+    /// Replaces the noise tables of a Perlin, marble or matte texture with
+    /// ones generated from `seed`. Other textures are returned unchanged.
+    pub fn with_noise_seed(self, seed: u64) -> Self {
+        let noise = Arc::new(Perlin::new(seed));
+        match self {
+            Texture::Perlin { scale, .. } => Texture::Perlin { scale, noise },
+            Texture::Marble { scale, .. } => Texture::Marble { scale, noise },
+            Texture::Matte { scale, .. } => Texture::Matte { scale, noise },
+            texture => texture,
+        }
+    }
+
+    /// Describes the texture for a scene file, or returns `None` for images
+    /// that weren't loaded from a file or that don't clamp.
+    pub fn describe(&self) -> Option<TextureDesc> {
+        Some(match self {
+            Texture::Constant(color) => TextureDesc::Constant(*color),
+            Texture::Checker { even, odd, scale } => TextureDesc::Checker {
+                even: Box::new(even.describe()?),
+                odd: Box::new(odd.describe()?),
+                scale: *scale,
+            },
+            Texture::Perlin { scale, noise } => TextureDesc::Perlin {
+                scale: *scale,
+                seed: noise_seed(noise),
+            },
+            Texture::Marble { scale, noise } => TextureDesc::Marble {
+                scale: *scale,
+                seed: noise_seed(noise),
+            },
+            Texture::Matte { scale, noise } => TextureDesc::Matte {
+                scale: *scale,
+                seed: noise_seed(noise),
+            },
+            Texture::Image { path, wrap, .. } => TextureDesc::Image {
+                path: path.clone().filter(|_| *wrap == [Wrap::Clamp; 2])?,
+            },
+            Texture::Scaled { factor, texture } => TextureDesc::Scaled {
+                factor: *factor,
+                texture: Box::new(texture.describe()?),
+            },
+        })
+    }
+}
+
+/// Seed to describe `noise` with, omitted for the shared tables.
+fn noise_seed(noise: &Perlin) -> Option<u64> {
+    Some(noise.seed()).filter(|&seed| seed != perlin::DEFAULT_SEED)
+}
 
 pub fn constant(color: Vec3) -> Texture {
-    Arc::new(move |_, _, _| color)
+    Texture::Constant(color)
 }
 
 pub fn checker(t0: Texture, t1: Texture, scale: f64) -> Texture {
-    Arc::new(move |u, v, p| {
-        let s = (scale * p).map(f64::sin).reduce(std::ops::Mul::mul);
-        if s < 0. {
-            t1(u, v, p)
-        } else {
-            t0(u, v, p)
-        }
-    })
+    Texture::Checker {
+        even: Box::new(t0),
+        odd: Box::new(t1),
+        scale,
+    }
 }
 
 pub fn marble_texture(scale: f64) -> Texture {
-    Arc::new(move |_, _, p| {
-        (0.5 * (1. + (scale * p.2 + 10. * perlin::turb(p, 7)).sin())) * Vec3(1., 1., 1.)
-    })
+    Texture::Marble {
+        scale,
+        noise: Perlin::shared(),
+    }
 }
 
 pub fn perlin(scale: f64) -> Texture {
-    Arc::new(move |_, _, p| Vec3::from(perlin::turb(scale * p, 7)))
+    Texture::Perlin {
+        scale,
+        noise: Perlin::shared(),
+    }
 }
 
 pub fn matte(scale: f64) -> Texture {
-    Arc::new(move |_, _, p| {
-        Vec3::from(0.5 * (1. + f64::sin(scale * p.0 + 5. * perlin::turb(scale * p, 7))))
-    })
+    Texture::Matte {
+        scale,
+        noise: Perlin::shared(),
+    }
 }
 
+/// Texture mapped from an image file, which may be a high dynamic range
+/// Radiance `.hdr` or OpenEXR `.exr` image.
 pub fn image_texture<P: std::convert::AsRef<std::path::Path>>(
     filename: P,
     // TODO: Remove boxed error
 ) -> Result<Texture, Box<dyn std::error::Error>> {
-    let (image, desc) = load_image(filename)?;
-    Ok(Arc::new(move |u, v, _| map_image(u, v, &image, desc)))
+    Ok(Texture::Image {
+        path: Some(filename.as_ref().to_path_buf()),
+        texels: Arc::new(load_image(filename)?),
+        wrap: [Wrap::Clamp; 2],
+    })
+}
+
+/// Texture mapped from an already decoded image, wrapping `u` and `v` by
+/// `wrap`.
+pub(crate) fn texels(image: Texels, wrap: [Wrap; 2]) -> Texture {
+    Texture::Image {
+        path: None,
+        texels: Arc::new(image),
+        wrap,
+    }
 }
@@ -0,0 +1,177 @@
+//! Display transforms, turning the linear radiance of a render into colors
+//! that fit the `[0, 1]` range of a display.
+
+use crate::vec3::Vec3;
+
+/// Operators compressing high dynamic range radiance into `[0, 1]`.
+#[derive(Copy, Clone, Debug)]
+pub enum ToneMap {
+    /// No compression: values above 1 are clipped.
+    Clamp,
+    /// [Reinhard et al.][reinhard] `L / (1 + L)` on luminance, which keeps
+    /// hues but never quite reaches white.
+    ///
+    /// [reinhard]: https://www.cs.utah.edu/docs/techreports/2002/pdf/UUCS-02-001.pdf
+    Reinhard,
+    /// Reinhard's operator extended so that luminance `white` and above map to
+    /// white. `white` must be positive; `extended_reinhard` checks it.
+    ExtendedReinhard { white: f64 },
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve, applied per channel.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2, applied per channel.
+    Hable,
+}
+
+impl ToneMap {
+    /// `ExtendedReinhard` mapping luminance `white` to white, or an error if
+    /// `white` isn't a positive number.
+    pub fn extended_reinhard(white: f64) -> Result<Self, String> {
+        if white.is_finite() && white > 0. {
+            Ok(ToneMap::ExtendedReinhard { white })
+        } else {
+            Err(format!("white point {} must be a positive number", white))
+        }
+    }
+}
+
+/// Parses `clamp`, `reinhard`, `aces`, `hable` or `extended-reinhard:WHITE`.
+impl std::str::FromStr for ToneMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "clamp" => Ok(ToneMap::Clamp),
+            "reinhard" => Ok(ToneMap::Reinhard),
+            "aces" => Ok(ToneMap::Aces),
+            "hable" => Ok(ToneMap::Hable),
+            _ => match s.strip_prefix("extended-reinhard:") {
+                Some(white) => {
+                    ToneMap::extended_reinhard(white.parse().map_err(|e| format!("{}", e))?)
+                }
+                None => Err(format!(
+                    "unknown tone map {:?}; use clamp, reinhard, extended-reinhard:WHITE, aces \
+                     or hable",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+/// How an image is prepared for display.
+#[derive(Copy, Clone, Debug)]
+pub struct DisplayTransform {
+    /// Exposure adjustment in stops: each stop doubles the brightness.
+    pub exposure: f64,
+    /// Operator compressing the exposed radiance into `[0, 1]`.
+    pub tone_map: ToneMap,
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform {
+            exposure: 0.,
+            tone_map: ToneMap::Clamp,
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// Maps linear radiance `col` to linear display values in `[0, 1]`.
+    pub fn apply(&self, col: Vec3) -> Vec3 {
+        let col = col * 2f64.powf(self.exposure);
+        let mapped = match self.tone_map {
+            ToneMap::Clamp => col,
+            ToneMap::Reinhard => scale_luminance(col, |l| l / (1. + l)),
+            ToneMap::ExtendedReinhard { white } => {
+                scale_luminance(col, |l| l * (1. + l / (white * white)) / (1. + l))
+            }
+            ToneMap::Aces => col.map(|x| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)),
+            ToneMap::Hable => {
+                const WHITE: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.;
+                col.map(|x| hable(EXPOSURE_BIAS * x) / hable(WHITE))
+            }
+        };
+        mapped.map(|x| x.clamp(0., 1.))
+    }
+
+    /// Maps linear radiance `col` to 8-bit sRGB values.
+    pub fn to_rgb8(&self, col: Vec3) -> [u8; 3] {
+        let col = self.apply(col).map(srgb_encode);
+        let to_u8 = |x: f64| (255.99 * x) as u8;
+        [to_u8(col.0), to_u8(col.1), to_u8(col.2)]
+    }
+}
+
+/// Rescales `col` so its luminance `l` becomes `f(l)`.
+fn scale_luminance(col: Vec3, f: impl Fn(f64) -> f64) -> Vec3 {
+    let l = col.luminance();
+    if l > 0. {
+        col * (f(l) / l)
+    } else {
+        Vec3::default()
+    }
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+/// The sRGB transfer function, encoding a linear value in `[0, 1]`.
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+/// The inverse of `srgb_encode`, decoding an sRGB value in `[0, 1]` to linear.
+pub fn srgb_decode(x: f64) -> f64 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert!(matches!("aces".parse(), Ok(ToneMap::Aces)));
+        assert!(matches!(
+            "extended-reinhard:4".parse(),
+            Ok(ToneMap::ExtendedReinhard { white }) if white == 4.
+        ));
+        for s in &[
+            "extended-reinhard:0",
+            "extended-reinhard:-1",
+            "extended-reinhard:inf",
+            "extended-reinhard:NaN",
+            "extended-reinhard:",
+            "filmic",
+        ] {
+            assert!(s.parse::<ToneMap>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn extended_reinhard_white() {
+        let display = DisplayTransform {
+            exposure: 0.,
+            tone_map: ToneMap::extended_reinhard(4.).unwrap(),
+        };
+        assert!((display.apply(Vec3::from(4.)) - Vec3::from(1.)).length() < 1e-9);
+        assert!(display.apply(Vec3::from(1.)).0 < 1.);
+    }
+}
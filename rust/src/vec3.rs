@@ -1,4 +1,5 @@
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
 
 /// A three-vector of floats, used as a color, coordinate, etc.
 ///
@@ -9,7 +10,7 @@ use rand::prelude::*;
 ///    rtiow::vec3::Axis::*` statement.
 /// 2. Using the `Channel` enum: `v[R]`, `v[G]`, `v[B]`. This requires a `use
 ///    rtiow::vec3::Channel::*` statement.
-#[derive(Copy, Clone, Default, Debug)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 // TODO(#2): Try f64 numbers
 pub struct Vec3(pub f64, pub f64, pub f64);
 
@@ -75,6 +76,19 @@ impl Vec3 {
         self.dot(*self).sqrt()
     }
 
+    /// Computes the relative luminance of the vector taken as a linear RGB
+    /// color, with Rec. 709 primaries.
+    #[inline]
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.0 + 0.7152 * self.1 + 0.0722 * self.2
+    }
+
+    /// Checks that no element of the vector is infinite or NaN.
+    #[inline]
+    pub fn is_finite(&self) -> bool {
+        self.0.is_finite() && self.1.is_finite() && self.2.is_finite()
+    }
+
     /// Produces a vector collinear with `self` but with unit length. That is,
     /// the result points the same direction as `self` relative to the origin.
     #[inline]